@@ -0,0 +1,33 @@
+use nonblockinghashmap::NonBlockingHashMap;
+use std::sync::Arc;
+use std::thread;
+
+fn main(){
+	let newmap = NonBlockingHashMap::<String,String>::new_with_size(1000);
+	let shared_map = Arc::new(newmap);
+	let nthreads = 30;
+	let put = 1000;
+	let get = 100000;
+
+	let mut handles = Vec::new();
+	for n in 0..nthreads {
+		let child_map_put = shared_map.clone();
+		let child_map_get = shared_map.clone();
+		handles.push(thread::spawn(move || {
+			for i in 0..put {
+				child_map_put.put(format!("key{}", i), format!("value{}_t{}", i, n));
+			}
+		}));
+
+		handles.push(thread::spawn(move || {
+			for i in 0..get {
+				let key = format!("key{}", i%put);
+				child_map_get.get(&key);
+			}
+		}));
+	}
+	for handle in handles {
+		handle.join().unwrap();
+	}
+	println!("{:?}", shared_map);
+}
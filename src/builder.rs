@@ -0,0 +1,188 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{NonBlockingHashMap, NonBlockingHashMapLong, MIN_SIZE, MIN_SIZE_LOG, RESIZE_CHURN_MS, now_ms};
+use crate::kvtable::REPROBE_LIMIT;
+use crate::copy::MIN_COPY_WORK;
+
+// ---Sizing Policy ----------------------------------------------------------------------------------------
+// The tuning knobs a map consults while probing, resizing and copying. Every map built without a
+// NonBlockingHashMapBuilder gets the defaults, which are the constants the Java original hard-codes.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Config {
+	pub _reprobe_limit: usize, // Probes past which a put claiming a slot in a full table resizes it, see reprobe_limit
+	pub _load_factor: f64, // Fraction of the slots live mappings may fill before a resize grows the table, at most 1/2
+	pub _growth_factor: usize, // A growing resize multiplies the table size by this, or its square when well past the load factor
+	pub _min_size_log: usize, // No table is ever smaller than 1<<_min_size_log slots
+	pub _max_initial_size: usize, // Larger initial sizes are clamped to this many mappings; unbounded by default
+	pub _min_copy_work: usize, // Slots a thread claims at a time when helping a copy
+}
+
+impl Default for Config {
+	fn default() -> Config {
+		Config {
+			_reprobe_limit: REPROBE_LIMIT,
+			_load_factor: 0.25,
+			_growth_factor: 2,
+			_min_size_log: MIN_SIZE_LOG,
+			_max_initial_size: usize::MAX,
+			_min_copy_work: MIN_COPY_WORK,
+		}
+	}
+}
+
+impl Config {
+	pub fn min_size(&self) -> usize {
+		1<<self._min_size_log
+	}
+
+	// Mappings a table of len slots is meant to hold.
+	pub fn quota(&self, len: usize) -> usize {
+		(len as f64 * self._load_factor) as usize
+	}
+
+	// Probes in a table of len slots before a put or get gives up on it, as in the Java original: the
+	// quota on top of _reprobe_limit. A table is only copied at the same size while it holds fewer
+	// mappings than its quota, so a fresh copy never has a cluster this long and the copy cannot loop.
+	pub fn reprobe_limit(&self, len: usize) -> usize {
+		self._reprobe_limit + self.quota(len)
+	}
+
+	// Whether a put that probed reprobe_cnt slots to claim a new one should resize the table: it is
+	// past _reprobe_limit and its claimed slots, live or dead, are past the quota.
+	pub fn table_full(&self, len: usize, slots: usize, reprobe_cnt: usize) -> bool {
+		reprobe_cnt >= self._reprobe_limit && slots >= self.reprobe_limit(len)
+	}
+
+	// Slots needed to hold n mappings at the load factor.
+	pub fn slots_for(&self, n: usize) -> usize {
+		(n as f64 / self._load_factor).ceil() as usize
+	}
+
+	// The smallest table, a power of 2 and at least min_size(), with at least n slots.
+	pub fn table_size(&self, n: usize) -> usize {
+		n.max(self.min_size()).checked_next_power_of_two().expect("capacity overflow")
+	}
+
+	// Size of the table to copy an oldlen-slot table into when a put runs out of room. Only atomically
+	// published statistics are used: the live mapping count, the number of key slots claimed in the old
+	// table, and the time of the last promotion. Never smaller than oldlen, as in the Java original: a
+	// table only shrinks through rehash or shrink_to_fit.
+	pub fn next_table_size(&self, oldlen: usize, sz: usize, slots: usize, last_resize_ms: u64) -> usize {
+		let quota = self.quota(oldlen);
+
+		if sz >= quota {
+			let newsz = oldlen.saturating_mul(self._growth_factor);
+			if sz >= quota<<1 {
+				return newsz.saturating_mul(self._growth_factor);
+			}
+			return newsz;
+		}
+		if sz < quota>>1 {
+			return oldlen; // Mostly removed keys: dropping them leaves plenty of room
+		}
+
+		// Same size: just drop the slots held by removed keys. Grow instead when that would copy the table
+		// over and over: if fewer slots are dead than live, live keys clustered past the reprobe limit are
+		// rebuilt exactly; and if most are dead but the last resize was so recent, keys are churning.
+		if slots < sz<<1 || now_ms() <= last_resize_ms + RESIZE_CHURN_MS {
+			return oldlen.saturating_mul(self._growth_factor);
+		}
+		oldlen
+	}
+
+	// Slots for a table rebuilt around sz live mappings by rehash or shrink_to_fit: room for as many again
+	// before they reach the load factor, so the next inserts do not resize it straight back.
+	pub fn rebuilt_size(&self, sz: usize) -> usize {
+		self.slots_for(sz<<1)
+	}
+}
+
+// ---Map Builder ------------------------------------------------------------------------------------------
+// Builds a NonBlockingHashMap with its own sizing policy, e.g. a short reprobe limit and a low load factor
+// for a small hot map, or a large initial size and bigger copy chunks for a huge one. Every setting not
+// given keeps the default used by NonBlockingHashMap::new.
+pub struct NonBlockingHashMapBuilder<S = RandomState> {
+	_config: Config,
+	_initial_size: usize,
+	_hasher: S,
+}
+
+impl NonBlockingHashMapBuilder<RandomState> {
+
+	pub fn new() -> NonBlockingHashMapBuilder<RandomState> {
+		NonBlockingHashMapBuilder { _config: Config::default(), _initial_size: MIN_SIZE, _hasher: RandomState::new() }
+	}
+}
+
+impl<S> NonBlockingHashMapBuilder<S> {
+	pub const MAX_REPROBE_LIMIT: usize = 64; // Longer probes cost more than resizing would
+
+	// Room for this many mappings before the first resize, up to max_initial_size.
+	pub fn initial_size(mut self, initial_sz: usize) -> NonBlockingHashMapBuilder<S> {
+		self._initial_size = initial_sz;
+		self
+	}
+
+	// Number of slots a put probes, once the table holds its quota of claimed slots, before it resizes the
+	// table; a table's full reprobe limit is this plus its quota. Between 1 and MAX_REPROBE_LIMIT.
+	pub fn reprobe_limit(mut self, limit: usize) -> NonBlockingHashMapBuilder<S> {
+		assert!(limit > 0 && limit <= Self::MAX_REPROBE_LIMIT);
+		self._config._reprobe_limit = limit;
+		self
+	}
+
+	// Fraction of the table live mappings may fill before a resize grows it, in (0, 1/2]. Open addressing
+	// past half full makes probes long and clusters likely. Tables are also sized to this fraction when
+	// created, reserved or shrunk.
+	pub fn load_factor(mut self, load_factor: f64) -> NonBlockingHashMapBuilder<S> {
+		assert!(load_factor > 0.0 && load_factor <= 0.5);
+		self._config._load_factor = load_factor;
+		self
+	}
+
+	// How many times larger a growing resize makes the table; at least 2.
+	pub fn growth_factor(mut self, growth_factor: usize) -> NonBlockingHashMapBuilder<S> {
+		assert!(growth_factor >= 2);
+		self._config._growth_factor = growth_factor;
+		self
+	}
+
+	// The smallest table the map will ever use, rounded up to a power of 2.
+	pub fn min_size(mut self, min_sz: usize) -> NonBlockingHashMapBuilder<S> {
+		self._config._min_size_log = min_sz.max(1).next_power_of_two().trailing_zeros() as usize;
+		self
+	}
+
+	// Initial sizes above this many mappings are clamped to it. There is no limit unless one is set.
+	pub fn max_initial_size(mut self, max_sz: usize) -> NonBlockingHashMapBuilder<S> {
+		self._config._max_initial_size = max_sz;
+		self
+	}
+
+	// Number of slots a thread claims at a time when it helps copy a table into a resized one.
+	pub fn min_copy_work(mut self, slots: usize) -> NonBlockingHashMapBuilder<S> {
+		assert!(slots > 0);
+		self._config._min_copy_work = slots;
+		self
+	}
+
+	pub fn hasher<T>(self, hasher: T) -> NonBlockingHashMapBuilder<T> {
+		NonBlockingHashMapBuilder { _config: self._config, _initial_size: self._initial_size, _hasher: hasher }
+	}
+
+	pub fn build<K: Eq + Hash, V>(self) -> NonBlockingHashMap<K, V, S> where S: BuildHasher {
+		NonBlockingHashMap::with_config(self._initial_size, self._hasher, self._config)
+	}
+
+	// A NonBlockingHashMapLong with this sizing policy. Its keys are spread directly, so the hasher is unused.
+	pub fn build_long(self) -> NonBlockingHashMapLong {
+		NonBlockingHashMapLong::with_config(self._initial_size, self._config)
+	}
+}
+
+impl Default for NonBlockingHashMapBuilder<RandomState> {
+	fn default() -> NonBlockingHashMapBuilder<RandomState> {
+		NonBlockingHashMapBuilder::new()
+	}
+}
@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicPtr, AtomicUsize};
+
+use crate::MEMORY_ORDERING;
+
+pub(crate) const MIN_COPY_WORK: usize = 1024;
+
+// ---Table Copy -------------------------------------------------------------------------------------------
+// The protocol every structure in the crate uses to move into a resized table, after help_copy in the Java
+// original. Threads claim chunks of old slots through _copy_idx and copy them; a slot that reaches its
+// final state counts once towards _copy_done, and whoever completes the count promotes the new table.
+// Once every chunk has been claimed, threads that still find the copy unfinished sweep the whole table.
+pub(crate) struct CopyState {
+	_copy_done: AtomicUsize, // Old slots copied to their final state
+	_copy_idx: AtomicUsize, // Start of the next chunk to claim
+}
+
+impl CopyState {
+	pub fn new() -> CopyState {
+		CopyState { _copy_done: AtomicUsize::new(0), _copy_idx: AtomicUsize::new(0) }
+	}
+}
+
+// A table that can be copied into a newer one.
+pub(crate) trait CopyTable {
+	fn table_len(&self) -> usize;
+	fn copy_state(&self) -> &CopyState;
+	fn newer_table(&self) -> *mut Self; // Null until a resize installs the copy target
+}
+
+// A structure that resizes by copying its top table, which must be pinned while these run. Implementors
+// supply how one slot is copied and what happens to a table once it has been promoted away.
+pub(crate) trait Resizable {
+	type Table: CopyTable;
+
+	fn top(&self) -> &AtomicPtr<Self::Table>;
+
+	// Move slot idx of oldtable to its final state. Returns true if this call got it there.
+	fn copy_slot(&self, oldtable: *mut Self::Table, idx: usize) -> bool;
+
+	// Slots a thread claims at a time when it helps a copy.
+	fn min_copy_work(&self) -> usize;
+
+	// Called once oldtable has been swapped out of the top, typically to retire it.
+	fn promoted(&self, oldtable: *mut Self::Table);
+
+	// Help any resize in progress all the way through, and return the table that is left on top.
+	fn finish_copy(&self) -> *mut Self::Table {
+		let mut table = self.top().load(MEMORY_ORDERING);
+		unsafe {
+			while !(*table).newer_table().is_null() {
+				self.help_copy_impl(table, true);
+				table = self.top().load(MEMORY_ORDERING);
+			}
+		}
+		table
+	}
+
+	// Copy a chunk of the top table, if it is being resized.
+	fn help_copy(&self) {
+		let table = self.top().load(MEMORY_ORDERING);
+		if unsafe {!(*table).newer_table().is_null()} {
+			self.help_copy_impl(table, false);
+		}
+	}
+
+	// Copy slot idx of oldtable, help the copy along if should_help, and return the table to retry in.
+	fn copy_slot_and_check(&self, oldtable: *mut Self::Table, idx: usize, should_help: bool) -> *mut Self::Table {
+		unsafe {
+			assert!(!(*oldtable).newer_table().is_null());
+			if self.copy_slot(oldtable, idx) {
+				self.copy_check_and_promote(oldtable, 1);
+			}
+			if should_help {
+				self.help_copy();
+			}
+			(*oldtable).newer_table()
+		}
+	}
+
+	// Count work_done more slots of oldtable as copied, and promote its copy target if that finished it.
+	fn copy_check_and_promote(&self, oldtable: *mut Self::Table, work_done: usize) {
+		unsafe {
+			let oldlen = (*oldtable).table_len();
+			let copy_done = (*oldtable).copy_state()._copy_done.fetch_add(work_done, MEMORY_ORDERING);
+			assert!(copy_done + work_done <= oldlen);
+
+			if copy_done + work_done == oldlen &&
+				self.top().compare_exchange(oldtable, (*oldtable).newer_table(), MEMORY_ORDERING, MEMORY_ORDERING).is_ok() {
+					self.promoted(oldtable);
+				}
+		}
+	}
+
+	// Claim chunks of oldtable and copy them: one chunk, or until the copy is done if copy_all.
+	fn help_copy_impl(&self, oldtable: *mut Self::Table, copy_all: bool) {
+		unsafe {
+			assert!(!(*oldtable).newer_table().is_null());
+			let state = (*oldtable).copy_state();
+			let oldlen = (*oldtable).table_len();
+			let min_copy_work = oldlen.min(self.min_copy_work());
+			let mut panic_start = false;
+			let mut copy_idx = 0;
+
+			while state._copy_done.load(MEMORY_ORDERING) < oldlen {
+				if !panic_start {
+					copy_idx = state._copy_idx.load(MEMORY_ORDERING);
+					while copy_idx < oldlen<<1 &&
+						state._copy_idx.compare_exchange(copy_idx, copy_idx + min_copy_work, MEMORY_ORDERING, MEMORY_ORDERING).is_err() {
+							copy_idx = state._copy_idx.load(MEMORY_ORDERING);
+						}
+					if copy_idx >= oldlen<<1 {
+						panic_start = true; // Every chunk was claimed twice over and the copy is still not done
+					}
+				}
+				let mut work_done = 0;
+				for i in 0..min_copy_work {
+					if self.copy_slot(oldtable, (copy_idx+i)%oldlen) {
+						work_done += 1;
+					}
+				}
+				if work_done > 0 {
+					self.copy_check_and_promote(oldtable, work_done);
+				}
+
+				copy_idx += min_copy_work;
+
+				if !copy_all && !panic_start {
+					return;
+				}
+			}
+			self.copy_check_and_promote(oldtable, 0); // Promote, in case the thread that finished the count has not yet
+		}
+	}
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicIsize, Ordering::SeqCst};
+
+// ---Striped Counter --------------------------------------------------------------------------------------
+// A counter in the spirit of Cliff Click's ConcurrentAutoTable: updates are spread over a number of
+// independent stripes so that threads rarely fight over the same cache line, and reads sum them up.
+// Individual stripes may go negative; only the sum is meaningful.
+
+pub const COUNTER_STRIPES: usize = 64; // Must be a power of 2
+
+#[repr(align(64))] // Keep each stripe on its own cache line
+struct Stripe {
+	_count: AtomicIsize,
+}
+
+pub struct Counter {
+	_stripes: Box<[Stripe]>,
+}
+
+impl Counter {
+	pub fn new() -> Counter {
+		Counter {
+			_stripes: (0..COUNTER_STRIPES).map(|_| Stripe { _count: AtomicIsize::new(0) }).collect(),
+		}
+	}
+
+	pub fn add(&self, x: isize) {
+		self._stripes[stripe_idx(COUNTER_STRIPES)]._count.fetch_add(x, SeqCst);
+	}
+
+	pub fn sum(&self) -> isize {
+		self._stripes.iter().map(|stripe| stripe._count.load(SeqCst)).sum()
+	}
+}
+
+// Threads run on distinct stacks, so the address of a local is a cheap per-thread hash. stripes must be a
+// power of 2.
+pub fn stripe_idx(stripes: usize) -> usize {
+	let marker = 0;
+	let mut h = (&marker as *const i32 as usize) >> 4;
+	h ^= h >> 7;
+	h ^= h >> 13;
+	h & (stripes-1)
+}
@@ -0,0 +1,156 @@
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering::SeqCst};
+
+use crate::counter::stripe_idx;
+
+// ---Epoch-Based Reclamation ------------------------------------------------------------------------------
+// Every operation on the map runs inside a Guard, which pins the thread to the current global epoch.
+// Anything unlinked from the map (a replaced Value, a KVs table that was promoted away, ...) is retired
+// tagged with the epoch it was retired in, and is only freed once the global epoch has moved two steps
+// past that tag. The epoch can only advance from e to e+1 once nobody is pinned at e-1, so at any time
+// pinned threads are in {e-1, e}, and two advances guarantee that every thread that could have loaded
+// the pointer before it was unlinked has unpinned.
+//
+// Pinned threads are counted per epoch in stripes, as Counter spreads its updates, so that pinning and
+// unpinning rarely touch a cache line another thread is using. Only an advance sums over the stripes.
+
+pub const EPOCHS: usize = 3;
+pub const PIN_STRIPES: usize = 16; // Must be a power of 2
+
+#[repr(align(64))] // Keep each stripe on its own cache line
+struct PinStripe {
+	_active: [AtomicUsize; EPOCHS], // Threads pinned through this stripe, by epoch
+}
+
+struct Garbage {
+	_ptr: *mut u8,
+	_free: fn(*mut u8),
+	_epoch: usize,
+	_next: *mut Garbage,
+}
+
+pub struct Collector {
+	_epoch: AtomicUsize,
+	_stripes: Box<[PinStripe]>,
+	_garbage: AtomicPtr<Garbage>,
+}
+
+impl Collector {
+	pub fn new() -> Collector {
+		Collector {
+			_epoch: AtomicUsize::new(0),
+			_stripes: (0..PIN_STRIPES).map(|_| PinStripe { _active: [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)] }).collect(),
+			_garbage: AtomicPtr::new(ptr::null_mut()),
+		}
+	}
+
+	pub fn pin(&self) -> Guard<'_> {
+		let stripe = stripe_idx(PIN_STRIPES); // Unpinning must find the same stripe, so the guard keeps it
+		loop {
+			let epoch = self._epoch.load(SeqCst);
+			self._stripes[stripe]._active[epoch % EPOCHS].fetch_add(1, SeqCst);
+			if self._epoch.load(SeqCst) == epoch {
+				return Guard { _collector: self, _stripe: stripe, _epoch: epoch };
+			}
+			// The epoch moved while we were registering; we may not be counted in the right slot.
+			self._stripes[stripe]._active[epoch % EPOCHS].fetch_sub(1, SeqCst);
+		}
+	}
+
+	pub fn epoch(&self) -> usize {
+		self._epoch.load(SeqCst)
+	}
+
+	// Hand an unlinked allocation to the collector. free is called on ptr once no thread can observe it.
+	pub fn retire(&self, ptr: *mut u8, free: fn(*mut u8)) {
+		let node = Box::into_raw(Box::new(Garbage { _ptr: ptr, _free: free, _epoch: self.epoch(), _next: ptr::null_mut() }));
+		self.push(node);
+	}
+
+	fn push(&self, node: *mut Garbage) {
+		unsafe {
+			loop {
+				let head = self._garbage.load(SeqCst);
+				(*node)._next = head;
+				if self._garbage.compare_exchange(head, node, SeqCst, SeqCst).is_ok() {
+					return;
+				}
+			}
+		}
+	}
+
+	pub fn try_advance(&self) -> bool {
+		let epoch = self._epoch.load(SeqCst);
+		let prev = (epoch + EPOCHS - 1) % EPOCHS;
+		if self._stripes.iter().any(|stripe| stripe._active[prev].load(SeqCst) != 0) {
+			return false;
+		}
+		self._epoch.compare_exchange(epoch, epoch + 1, SeqCst, SeqCst).is_ok()
+	}
+
+	// Free every retired allocation that is at least two epochs old, and put the rest back.
+	pub fn collect(&self) {
+		unsafe {
+			let mut node = self._garbage.swap(ptr::null_mut(), SeqCst);
+			// Read the epoch only after taking the list, so that no node carries a newer tag.
+			let epoch = self.epoch();
+			while !node.is_null() {
+				let next = (*node)._next;
+				if epoch - (*node)._epoch >= 2 {
+					((*node)._free)((*node)._ptr);
+					drop(Box::from_raw(node));
+				}
+				else {
+					self.push(node);
+				}
+				node = next;
+			}
+		}
+	}
+
+	pub fn has_garbage(&self) -> bool {
+		!self._garbage.load(SeqCst).is_null()
+	}
+
+	// Free everything retired so far. Only sound when no other thread can be pinned, which &mut self
+	// guarantees.
+	pub fn flush(&mut self) {
+		unsafe {
+			let mut node = self._garbage.swap(ptr::null_mut(), SeqCst);
+			while !node.is_null() {
+				let next = (*node)._next;
+				((*node)._free)((*node)._ptr);
+				drop(Box::from_raw(node));
+				node = next;
+			}
+		}
+	}
+}
+
+impl Drop for Collector {
+	fn drop(&mut self) {
+		self.flush();
+	}
+}
+
+pub struct Guard<'a> {
+	_collector: &'a Collector,
+	_stripe: usize,
+	_epoch: usize,
+}
+
+impl Drop for Guard<'_> {
+	fn drop(&mut self) {
+		self._collector._stripes[self._stripe]._active[self._epoch % EPOCHS].fetch_sub(1, SeqCst);
+		if self._collector.has_garbage() && self._collector.try_advance() {
+			self._collector.collect();
+		}
+	}
+}
+
+// Free functions handed to Collector::retire.
+pub fn free_box<T>(ptr: *mut u8) {
+	unsafe {
+		drop(Box::from_raw(ptr as *mut T));
+	}
+}
@@ -0,0 +1,160 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::fmt::Debug;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::ops::Deref;
+
+use crate::{NonBlockingHashMap, Entries, Ref, MIN_SIZE};
+
+// ---Identity Keys ----------------------------------------------------------------------------------------
+// The address a key points to: all an identity map ever hashes or compares.
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+struct Address(usize);
+
+impl Address {
+	fn of<T: ?Sized>(target: &T) -> Address {
+		Address(target as *const T as *const u8 as usize)
+	}
+}
+
+// A key stored together with its address, so lookups never touch the key itself.
+struct IdentityKey<K> {
+	_addr: Address,
+	_key: K,
+}
+
+impl<K> PartialEq for IdentityKey<K> {
+	fn eq(&self, other: &IdentityKey<K>) -> bool {
+		self._addr==other._addr
+	}
+}
+
+impl<K> Eq for IdentityKey<K> {}
+
+impl<K> Hash for IdentityKey<K> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self._addr.hash(state);
+	}
+}
+
+// Lets get() probe with a bare Address; hashes and compares exactly like the key.
+impl<K> Borrow<Address> for IdentityKey<K> {
+	fn borrow(&self) -> &Address {
+		&self._addr
+	}
+}
+
+// Hands the address through unchanged; the map's spread step does the mixing.
+#[derive(Default)]
+struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		for b in bytes {
+			self.0 = (self.0<<8) | *b as u64;
+		}
+	}
+
+	fn write_usize(&mut self, n: usize) {
+		self.0 = n as u64;
+	}
+}
+
+type IdentityMap<K, V> = NonBlockingHashMap<IdentityKey<K>, V, BuildHasherDefault<AddressHasher>>;
+
+// ---Identity Hash Map ------------------------------------------------------------------------------------
+// A NonBlockingHashMap keyed by pointer identity, after NonBlockingIdentityHashMap in the Java original. K is
+// a pointer type (&T, Box<T>, Arc<T>, ...) and two keys are equal only if they point to the same object, so
+// T needs neither Hash nor Eq. Zero-sized objects may share an address and so collide.
+pub struct NonBlockingIdentityHashMap<K, V> {
+	_map: IdentityMap<K, V>,
+}
+
+impl<K: Deref, V> NonBlockingIdentityHashMap<K, V> {
+
+	pub fn new() -> NonBlockingIdentityHashMap<K, V> {
+		NonBlockingIdentityHashMap::new_with_size(MIN_SIZE)
+	}
+
+	pub fn new_with_size(initial_sz: usize) -> NonBlockingIdentityHashMap<K, V> {
+		NonBlockingIdentityHashMap { _map: NonBlockingHashMap::with_capacity_and_hasher(initial_sz, BuildHasherDefault::default()) }
+	}
+
+	fn identity_key(key: K) -> IdentityKey<K> {
+		IdentityKey { _addr: Address::of(&*key), _key: key }
+	}
+
+	// Returns the value that was replaced, or None if key was absent.
+	pub fn put(&self, key: K, newval: V) -> Option<Ref<'_, V>> {
+		self._map.put(Self::identity_key(key), newval)
+	}
+
+	// Insert newval only if key is absent. Returns the value already mapped, if any.
+	pub fn put_if_absent(&self, key: K, newval: V) -> Option<Ref<'_, V>> {
+		self._map.put_if_absent(Self::identity_key(key), newval)
+	}
+
+	// Overwrite the value only if a key points to target. Returns the value that was replaced.
+	pub fn replace(&self, target: &K::Target, newval: V) -> Option<Ref<'_, V>> {
+		self._map.replace(&Address::of(target), newval)
+	}
+
+	// Returns the value that was removed, or None if no key points to target.
+	pub fn remove(&self, target: &K::Target) -> Option<Ref<'_, V>> {
+		self._map.remove(&Address::of(target))
+	}
+
+	// Look up the key pointing to target.
+	pub fn get(&self, target: &K::Target) -> Option<Ref<'_, V>> {
+		self._map.get(&Address::of(target))
+	}
+
+	pub fn contains_key(&self, target: &K::Target) -> bool {
+		self._map.contains_key(&Address::of(target))
+	}
+
+	pub fn len(&self) -> usize {
+		self._map.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self._map.is_empty()
+	}
+
+	pub fn capacity(&self) -> usize {
+		self._map.capacity()
+	}
+
+	// Weakly consistent, like NonBlockingHashMap::iter.
+	pub fn iter(&self) -> IterIdentity<'_, K, V> {
+		IterIdentity { _iter: self._map.iter() }
+	}
+}
+
+pub struct IterIdentity<'a, K, V> {
+	_iter: Entries<'a, IdentityKey<K>, V, BuildHasherDefault<AddressHasher>>,
+}
+
+impl<'a, K, V> Iterator for IterIdentity<'a, K, V> {
+	type Item = (Ref<'a, K>, Ref<'a, V>);
+
+	fn next(&mut self) -> Option<(Ref<'a, K>, Ref<'a, V>)> {
+		self._iter.next().map(|(k, v)| (k.map(|k| &k._key), v))
+	}
+}
+
+impl<K: Deref, V> Default for NonBlockingIdentityHashMap<K, V> {
+	fn default() -> NonBlockingIdentityHashMap<K, V> {
+		NonBlockingIdentityHashMap::new()
+	}
+}
+
+impl<K: Deref + Debug, V: Debug> Debug for NonBlockingIdentityHashMap<K, V> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_map().entries(self.iter()).finish()
+	}
+}
@@ -0,0 +1,192 @@
+use std::hash::{Hash, Hasher};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+// ---Key Slots -------------------------------------------------------------------------------------------
+// A key slot holds a *mut Key<T>. As with values, null is Empty and the address of the static sentinel is
+// TombStone, so a new table needs no per-slot allocation and only real keys are ever boxed.
+static TOMBSTONE: usize = 0;
+
+// A Key is shared by every table it has been copied into; _refs counts the table slots holding it.
+pub struct Key<T> {
+	pub _key: *mut T,
+	pub _refs: AtomicUsize,
+}
+
+impl<T> Key<T> {
+	pub fn new(k: T) -> Key<T> {
+		Key { _key: Box::into_raw(Box::new(k)), _refs: AtomicUsize::new(0) }
+	}
+
+	pub fn tombstone() -> *mut Key<T> {
+		ptr::addr_of!(TOMBSTONE) as *mut Key<T>
+	}
+
+	pub fn is_empty(k: *mut Key<T>) -> bool {
+		k.is_null()
+	}
+
+	pub fn is_tombstone(k: *mut Key<T>) -> bool {
+		k==Key::tombstone()
+	}
+
+	// A real key, neither Empty nor TombStone.
+	pub fn is_key(k: *mut Key<T>) -> bool {
+		!Key::is_empty(k) && !Key::is_tombstone(k)
+	}
+
+	pub fn get_key(&self) -> *mut T {
+		assert!(!self._key.is_null());
+		self._key
+	}
+
+}
+
+// ---Hash Function--------------------------------------------------------------------------------------
+// Mixes the bits of whatever hash the map's hasher produced, so that weak hashes still probe well.
+pub fn spread(h: u64) -> u64 {
+	let mut h = h;
+	h = h.wrapping_add((h << 15) ^ 0xffffcd7d);
+	h ^= h >> 10;
+	h = h.wrapping_add(h << 3);
+	h ^= h >> 6;
+	h = h.wrapping_add((h << 2).wrapping_add(h << 14));
+	h ^ (h >> 16)
+}
+
+impl<T: Hash> Hash for Key<T>{
+	fn hash<H: Hasher>(&self, state: &mut H){
+		unsafe {(*self._key).hash(state)};
+	}
+}
+
+impl<T> Key<T> {
+	pub fn refs(&self) -> usize {
+		self._refs.load(SeqCst)
+	}
+
+	pub fn acquire(&self) {
+		self._refs.fetch_add(1, SeqCst);
+	}
+
+	// Returns true if this was the last reference.
+	pub fn release(&self) -> bool {
+		self._refs.fetch_sub(1, SeqCst)==1
+	}
+}
+
+impl<T> Drop for Key<T> {
+	fn drop(&mut self){
+		if !self._key.is_null() {
+			unsafe {
+				drop(Box::from_raw(self._key));
+			}
+		}
+	}
+}
+
+// Drop one table reference to a Key, freeing it with the last one.
+pub fn release_key<T>(ptr: *mut u8) {
+	unsafe {
+		let key = ptr as *mut Key<T>;
+		if (*key).release() {
+			drop(Box::from_raw(key));
+		}
+	}
+}
+
+impl<T: PartialEq> PartialEq for Key<T>{
+	fn eq(&self, other: &Key<T>) -> bool{
+		self._key==other._key || unsafe {(*self._key)==(*other._key)}
+	}
+}
+
+// ---Value Slots -----------------------------------------------------------------------------------------
+// A value slot holds a tagged *mut Value<T>. Null is Empty and the address of a static sentinel is
+// TombStone, so neither is ever allocated, and the low bit marks a Prime: priming a value, or turning a
+// slot into a TombPrime, is a single CAS on the pointer. A primed value and its copy in the newer table
+// are the same Value, so values are matched by pointer. Values share TOMBSTONE with keys.
+const PRIME_TAG: usize = 1;
+
+// A real value. While it is being copied it sits in two tables at once; _refs counts the table slots
+// holding it, as for Key.
+pub struct Value<T> {
+	pub _refs: AtomicUsize,
+	pub _value: T,
+}
+
+impl<T> Value<T> {
+	pub fn new(v: T) -> Value<T> {
+		Value { _refs: AtomicUsize::new(0), _value: v }
+	}
+
+	pub fn empty() -> *mut Value<T> {
+		ptr::null_mut()
+	}
+
+	pub fn tombstone() -> *mut Value<T> {
+		ptr::addr_of!(TOMBSTONE) as *mut Value<T>
+	}
+
+	pub fn tombprime() -> *mut Value<T> {
+		Value::prime(Value::tombstone())
+	}
+
+	pub fn prime(v: *mut Value<T>) -> *mut Value<T> {
+		assert!(!Value::is_empty(v));
+		v.map_addr(|addr| addr | PRIME_TAG)
+	}
+
+	pub fn unprime(v: *mut Value<T>) -> *mut Value<T> {
+		v.map_addr(|addr| addr & !PRIME_TAG)
+	}
+
+	pub fn is_empty(v: *mut Value<T>) -> bool {
+		v.is_null()
+	}
+
+	// TombStone or TombPrime.
+	pub fn is_tombstone(v: *mut Value<T>) -> bool {
+		Value::unprime(v)==Value::tombstone()
+	}
+
+	pub fn is_prime(v: *mut Value<T>) -> bool {
+		v.addr() & PRIME_TAG != 0
+	}
+
+	pub fn is_tombprime(v: *mut Value<T>) -> bool {
+		v==Value::tombprime()
+	}
+
+	// A real value, primed or not.
+	pub fn is_value(v: *mut Value<T>) -> bool {
+		!Value::is_empty(v) && !Value::is_tombstone(v)
+	}
+
+	pub fn get_value(v: *mut Value<T>) -> *mut T {
+		assert!(Value::is_value(v) && !Value::is_prime(v));
+		unsafe { ptr::addr_of_mut!((*v)._value) }
+	}
+
+	pub fn refs(&self) -> usize {
+		self._refs.load(SeqCst)
+	}
+
+	pub fn acquire(&self) {
+		self._refs.fetch_add(1, SeqCst);
+	}
+
+	pub fn release(&self) -> bool {
+		self._refs.fetch_sub(1, SeqCst)==1
+	}
+}
+
+// Drop one table reference to a Value, freeing it with the last one.
+pub fn release_value<T>(ptr: *mut u8) {
+	unsafe {
+		let value = ptr as *mut Value<T>;
+		if (*value).release() {
+			drop(Box::from_raw(value));
+		}
+	}
+}
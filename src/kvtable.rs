@@ -0,0 +1,130 @@
+use std::alloc::{self, Layout};
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering::SeqCst};
+use crate::keyvalue::{Key, Value, release_key, release_value};
+use crate::counter::Counter;
+use crate::copy::{CopyState, CopyTable};
+
+pub const REPROBE_LIMIT: usize = 10;
+
+// An array of len T's, all bits zero, from one allocation and without touching each element; large
+// arrays come straight from the zero pages of the allocator. All zeroes must be a valid T.
+pub(crate) fn zeroed_slice<T>(len: usize) -> Box<[T]> {
+	assert!(len > 0);
+	let layout = Layout::array::<T>(len).expect("capacity overflow");
+	unsafe {
+		let ptr = alloc::alloc_zeroed(layout) as *mut T;
+		if ptr.is_null() { alloc::handle_alloc_error(layout); }
+		Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len))
+	}
+}
+
+// ---Hash Table Layer Node -------------------------------------------------------------------------------
+// One slot: its key, value and cached hash side by side, so a probe touches a single cache line. A zeroed
+// Slot is {Empty, Empty} with no hash published, which is what lets a table be one zeroed allocation.
+pub struct Slot<K,V> {
+	pub _key: AtomicPtr<Key<K>>,
+	pub _value: AtomicPtr<Value<V>>,
+	pub _hash: AtomicU64, // Full hash of the key, 0 until published
+}
+
+pub struct KVs<K,V> {
+	pub _table: Box<[Slot<K,V>]>,
+	pub _chm: CHM<K,V>,
+}
+
+impl<K,V> KVs<K,V>{
+	// size is the live mapping count: a table made by a resize shares it with the table it copies.
+	pub fn new(table_size: usize, size: Arc<Counter>) -> KVs<K,V>{
+		KVs {
+			_table: zeroed_slice(table_size), // Null is Empty for both keys and values
+			_chm: CHM::<K, V>::new(size),
+		}
+	}
+
+	pub fn get_key_nonatomic_at(&self, idx: usize) -> *mut Key<K> {
+		self._table[idx]._key.load(SeqCst)
+	}
+
+	pub fn get_value_nonatomic_at(&self, idx: usize) -> *mut Value<V> {
+		self._table[idx]._value.load(SeqCst)
+	}
+
+	// The full hash of the key in slot idx, or 0 if the thread that claimed the slot has not published it
+	// yet (or the hash really is 0); either way the caller must then fall back to the key itself.
+	pub fn get_hash_at(&self, idx: usize) -> u64 {
+		self._table[idx]._hash.load(SeqCst)
+	}
+
+	// Only the thread whose key CAS claimed slot idx publishes its hash, and only once.
+	pub fn set_hash_at(&self, idx: usize, fullhash: u64) {
+		self._table[idx]._hash.store(fullhash, SeqCst);
+	}
+
+	pub fn len(&self) -> usize {
+		self._table.len()
+	}
+}
+
+impl<K,V> CopyTable for KVs<K,V> {
+	fn table_len(&self) -> usize {
+		self.len()
+	}
+
+	fn copy_state(&self) -> &CopyState {
+		&self._chm._copy
+	}
+
+	fn newer_table(&self) -> *mut KVs<K,V> {
+		self._chm.get_newkvs_nonatomic()
+	}
+}
+
+// Keys and values may still be referenced by newer tables, so only this table's reference to them is
+// dropped. Sentinels were never allocated and are skipped; a slot whose key never became real holds no
+// value either. The newer tables themselves are owned by the map, not by _chm.
+impl<K,V> Drop for KVs<K,V> {
+	fn drop(&mut self) {
+		for slot in self._table.iter() {
+			let key = slot._key.load(SeqCst);
+			if !Key::is_key(key) { continue; }
+			release_key::<K>(key as *mut u8);
+			let value = slot._value.load(SeqCst);
+			if Value::is_value(value) {
+				release_value::<V>(Value::unprime(value) as *mut u8);
+			}
+		}
+	}
+
+}
+
+// ---Structure for resizing -------------------------------------------------------
+
+#[allow(clippy::upper_case_acronyms)] // After the ConcurrentHashMap-style helper in the Java original
+pub struct CHM<K,V> {
+	pub _newkvs: AtomicPtr<KVs<K,V>>,
+	pub _slots: AtomicUsize,
+	pub _copy: CopyState,
+	pub _size: Arc<Counter>, // Number of live mappings
+}
+
+impl<K,V> CHM<K,V> {
+	pub fn new(size: Arc<Counter>) -> CHM<K,V>{
+		CHM {
+			_newkvs: AtomicPtr::new(ptr::null_mut()),
+			_slots: AtomicUsize::new(0),
+			_copy: CopyState::new(),
+			_size: size,
+		}
+	}
+
+	pub fn get_newkvs_nonatomic(&self) -> *mut KVs<K,V> {
+		self._newkvs.load(SeqCst)
+	}
+
+	pub fn has_newkvs(&self) -> bool {
+		!self._newkvs.load(SeqCst).is_null()
+	}
+
+}
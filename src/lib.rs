@@ -0,0 +1,1849 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use keyvalue::{Key, Value, release_value, spread};
+use kvtable::KVs;
+use epoch::{Collector, Guard, free_box};
+use counter::Counter;
+use builder::Config;
+use copy::Resizable;
+use self::MatchingTypes::{MatchAll, MatchAllNotEmpty, MatchValue};
+use self::ComputeAction::{Keep, Put, PutGiven, Remove};
+pub use self::Entry::{Occupied, Vacant};
+pub use longmap::{NonBlockingHashMapLong, IterLong};
+pub use set::NonBlockingHashSet;
+pub use setint::{NonBlockingSetInt, IterSetInt};
+pub use identity::{NonBlockingIdentityHashMap, IterIdentity};
+pub use builder::NonBlockingHashMapBuilder;
+
+mod keyvalue;
+mod kvtable;
+mod epoch;
+mod counter;
+mod longmap;
+mod set;
+mod setint;
+mod identity;
+mod builder;
+mod copy;
+
+const MIN_SIZE_LOG: usize = 3;
+const MIN_SIZE: usize = 1<<MIN_SIZE_LOG;
+
+const MEMORY_ORDERING: Ordering = Ordering::SeqCst;
+
+// A resize this soon after the last promotion, with most claimed slots dead, grows a churning table;
+// see Config::next_table_size.
+const RESIZE_CHURN_MS: u64 = 1000;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum MatchingTypes {
+	MatchAll,
+	MatchAllNotEmpty,
+	MatchValue,
+	FromCopySlot
+}
+
+// What a compute-style closure wants done with the mapping it was shown.
+enum ComputeAction<V> {
+	Keep,
+	Put(V),
+	PutGiven, // Put the value handed to compute_impl
+	Remove,
+}
+
+// Milliseconds since the Unix epoch, for the resize heuristics.
+fn now_ms() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+// ---Hash Map --------------------------------------------------------------------
+// All operations take &self: every piece of mutable state is reached through an atomic,
+// so the map is Send + Sync whenever K and V are, and can be shared through a plain Arc.
+// S builds the key hasher (RandomState by default); its output goes through a fixed spreading step on top.
+pub struct NonBlockingHashMap<K, V, S = RandomState> {
+	_kvs: AtomicPtr<KVs<K,V>>,
+	_last_resize_ms: AtomicU64, // Time of the last table promotion
+	_collector: Collector, // Frees retired tables, keys and values once no thread can see them
+	_config: Config, // Sizing policy, see NonBlockingHashMapBuilder
+	_initial_len: usize, // Length of the table the map was built with, which clear starts over from
+	_hasher: S,
+	_marker: PhantomData<(K, V)>,
+}
+
+// Keys and values are handed between threads, and readers on any thread borrow them.
+unsafe impl<K: Send + Sync, V: Send + Sync, S: Send> Send for NonBlockingHashMap<K, V, S> {}
+unsafe impl<K: Send + Sync, V: Send + Sync, S: Sync> Sync for NonBlockingHashMap<K, V, S> {}
+
+impl<K: Eq + Hash, V> NonBlockingHashMap<K, V, RandomState> {
+
+	pub fn new() -> NonBlockingHashMap<K, V, RandomState> {
+		NonBlockingHashMap::new_with_size(MIN_SIZE)
+	}
+
+	pub fn new_with_size(initial_sz: usize) -> NonBlockingHashMap<K, V, RandomState> {
+		NonBlockingHashMap::with_capacity_and_hasher(initial_sz, RandomState::new())
+	}
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> NonBlockingHashMap<K, V, S> {
+
+	pub fn with_hasher(hasher: S) -> NonBlockingHashMap<K, V, S> {
+		NonBlockingHashMap::with_capacity_and_hasher(MIN_SIZE, hasher)
+	}
+
+	pub fn with_capacity_and_hasher(initial_sz: usize, hasher: S) -> NonBlockingHashMap<K, V, S> {
+		NonBlockingHashMap::with_config(initial_sz, hasher, Config::default())
+	}
+
+	fn with_config(initial_sz: usize, hasher: S, config: Config) -> NonBlockingHashMap<K, V, S> {
+		let initial_sz = initial_sz.min(config._max_initial_size);
+		let len = config.table_size(config.slots_for(initial_sz));
+
+		NonBlockingHashMap {
+			_kvs: AtomicPtr::new(Box::into_raw(Box::new(KVs::<K,V>::new(len, Arc::new(Counter::new()))))),
+			_last_resize_ms: AtomicU64::new(now_ms()),
+			_collector: Collector::new(),
+			_config: config,
+			_initial_len: len,
+			_hasher: hasher,
+			_marker: PhantomData,
+		}
+	}
+
+	// The supplied hasher, followed by a fixed bit-mixing step that spreads weak hashes across the table.
+	fn hash_key<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+		spread(self._hasher.hash_one(key))
+	}
+
+	// Number of live mappings. Exact when the map is quiescent; under concurrent updates it is a
+	// value the count passed through recently.
+	pub fn size(&self) -> usize {
+		self.len()
+	}
+
+	// Number of slots in the current table.
+	pub fn capacity(&self) -> usize {
+		let _guard = self.pin();
+		unsafe {(*self.get_table_nonatomic()).len()}
+	}
+
+	// Every public operation holds a Guard while it touches the table; the *_impl functions assume
+	// the caller already does.
+	fn pin(&self) -> Guard<'_> {
+		self._collector.pin()
+	}
+
+	// A value that was swapped out of a slot; readers may still be looking at it. Sentinels are skipped.
+	fn retire_value(&self, value: *mut Value<V>) {
+		if Value::is_value(value) {
+			self._collector.retire(Value::unprime(value) as *mut u8, release_value::<V>);
+		}
+	}
+
+	// Free a Key or Value that this thread allocated but never managed to publish.
+	fn free_unpublished_key(key: *mut Key<K>) {
+		unsafe {
+			if (*key).refs()==0 { drop(Box::from_raw(key)); }
+		}
+	}
+
+	fn free_unpublished_value(value: *mut Value<V>) {
+		unsafe {
+			if Value::is_value(value) && (*value).refs()==0 { drop(Box::from_raw(value)); }
+		}
+	}
+
+	fn get_table_nonatomic(&self) -> *mut KVs<K,V>{
+		self._kvs.load(MEMORY_ORDERING)
+	}
+
+	// Start copying kvs into a table sized by the heuristics in resize_target, unless a copy is already
+	// under way. Returns the table being copied into.
+	fn resize(&self, kvs: *mut KVs<K,V>) -> *mut KVs<K,V> {
+		unsafe {
+			if (*kvs)._chm.has_newkvs() {
+				return (*kvs)._chm._newkvs.load(MEMORY_ORDERING);
+			}
+			let newsz = self.resize_target(kvs);
+			self.install_newkvs(kvs, newsz)
+		}
+	}
+
+	// Decide how big the next table should be, see Config::next_table_size.
+	fn resize_target(&self, kvs: *mut KVs<K,V>) -> usize {
+		unsafe {
+			let sz = (*kvs)._chm._size.sum().max(0) as usize;
+			self._config.next_table_size((*kvs).len(), sz, (*kvs)._chm._slots.load(MEMORY_ORDERING), self._last_resize_ms.load(MEMORY_ORDERING))
+		}
+	}
+
+	// Install a new table of at least newsz slots (rounded up to a power of 2) as the copy target of kvs.
+	// If another thread installed one first, ours is thrown away and theirs is returned.
+	fn install_newkvs(&self, kvs: *mut KVs<K,V>, newsz: usize) -> *mut KVs<K,V> {
+		unsafe {
+			let newlen = self._config.table_size(newsz);
+
+			if (*kvs)._chm.has_newkvs() {
+				return (*kvs)._chm._newkvs.load(MEMORY_ORDERING);
+			}
+
+			let mut newkvs: *mut KVs<K,V> = Box::into_raw(Box::new(KVs::<K,V>::new(newlen, (*kvs)._chm._size.clone())));
+
+			if (*kvs)._chm.has_newkvs() {
+				drop(Box::from_raw(newkvs));
+				return (*kvs)._chm._newkvs.load(MEMORY_ORDERING);
+			}
+
+			let oldkvs = (*kvs)._chm._newkvs.load(MEMORY_ORDERING);
+			if (*kvs)._chm._newkvs.compare_exchange(oldkvs, newkvs, MEMORY_ORDERING, MEMORY_ORDERING).is_err() {
+				drop(Box::from_raw(newkvs)); // Another thread installed its table first
+				newkvs = (*kvs)._chm._newkvs.load(MEMORY_ORDERING);
+			}
+			newkvs
+		}
+	}
+
+	// Copy the map into a fresh table sized for its current contents (see Config::rebuilt_size), dropping
+	// the slots held by removed keys, and wait for the copy to complete.
+	pub fn rehash(&self){
+		let _guard = self.pin();
+		let kvs = self.finish_copy();
+		self.install_newkvs(kvs, self._config.rebuilt_size(self.size()));
+		self.finish_copy();
+	}
+
+	// Make sure the table can hold at least n mappings without resizing.
+	pub fn reserve(&self, n: usize){
+		let _guard = self.pin();
+		loop {
+			let kvs = self.finish_copy();
+			let newsz = self._config.slots_for(n);
+			if unsafe {(*kvs).len()} >= newsz { return; }
+			self.install_newkvs(kvs, newsz);
+		}
+	}
+
+	// Shrink the table to the smallest size with room for the current mappings to double within the load
+	// factor; see Config::rebuilt_size.
+	pub fn shrink_to_fit(&self){
+		let _guard = self.pin();
+		let kvs = self.finish_copy();
+		let newsz = self._config.rebuilt_size(self.size());
+		let len = unsafe {(*kvs).len()};
+		if len > newsz && len > self._config.min_size() {
+			self.install_newkvs(kvs, newsz);
+			self.finish_copy();
+		}
+	}
+
+	// Remove every mapping at once by swapping in a fresh, empty table as large as the one the map was built
+	// with, so a map sized up front does not have to grow all over again. The old table, and any table a
+	// resize in progress installed after it, is retired as a whole rather than emptied slot by slot; it
+	// keeps its own live count, so operations that were already running on it and finish there (as if
+	// they had happened before the clear) do not disturb the new one.
+	pub fn clear(&self){
+		let _guard = self.pin();
+		let newkvs: *mut KVs<K,V> = Box::into_raw(Box::new(KVs::<K,V>::new(self._initial_len, Arc::new(Counter::new()))));
+		let mut kvs = self.get_table_nonatomic();
+		while let Err(current) = self._kvs.compare_exchange(kvs, newkvs, MEMORY_ORDERING, MEMORY_ORDERING) {
+			kvs = current; // A resize promoted a new table; clear that one instead
+		}
+		self._last_resize_ms.store(now_ms(), MEMORY_ORDERING);
+		// A thread still working on the old chain may yet install a resize target in it, but it is pinned,
+		// so the chain is walked only after it is done.
+		self._collector.retire(kvs as *mut u8, free_table_chain::<K,V>);
+	}
+
+	// Returns the value that was replaced, or None if key was absent.
+	pub fn put(&self, key: K, newval: V) -> Option<Ref<'_, V>>{
+		self.put_boxed(key, Box::into_raw(Box::new(Value::<V>::new(newval))), MatchAll, None)
+	}
+
+	// Like put, but hands back a clone of the value that was replaced.
+	pub fn insert(&self, key: K, newval: V) -> Option<V> where V: Clone {
+		self.put(key, newval).map(|oldval| (*oldval).clone())
+	}
+
+	// Insert newval only if key is absent, via {Key, Empty} -> {Key, Value} or {Key, TombStone} -> {Key, Value}.
+	// Returns the value already mapped (nothing was written), or None if newval was inserted.
+	pub fn put_if_absent(&self, key: K, newval: V) -> Option<Ref<'_, V>>{
+		self.put_boxed(key, Box::into_raw(Box::new(Value::<V>::new(newval))), MatchValue, Some(Value::tombstone()))
+	}
+
+	// Overwrite the value only if key is present. Probes with the borrowed key, like get: only an existing
+	// mapping is overwritten, so no key slot is ever claimed.
+	// Returns the value that was replaced, or None if key was absent (nothing was written).
+	pub fn replace<Q: Hash + Eq + ?Sized>(&self, key: &Q, newval: V) -> Option<Ref<'_, V>> where K: Borrow<Q> {
+		let guard = self.pin();
+		let putval: *mut Value<V> = Box::into_raw(Box::new(Value::<V>::new(newval)));
+		let returnval = self.replace_existing(self.hash_key(key), &|k| k.borrow()==key, &|_| true, putval);
+		let result = returnval.and_then(|v| Self::value_ref(guard, v));
+		Self::free_unpublished_value(putval);
+		result
+	}
+
+	// Overwrite the value only if key is currently mapped to a value equal to expval. Probes like replace.
+	// Returns true if the write happened.
+	// The value compared is the one the CAS swaps out, so the comparison holds at the moment of the write.
+	pub fn replace_if_eq<Q: Hash + Eq + ?Sized>(&self, key: &Q, expval: V, newval: V) -> bool where K: Borrow<Q>, V: PartialEq {
+		let _guard = self.pin();
+		let putval: *mut Value<V> = Box::into_raw(Box::new(Value::<V>::new(newval)));
+		let replaced = self.replace_existing(self.hash_key(key), &|k| k.borrow()==key, &|v| *v==expval, putval).is_some();
+		Self::free_unpublished_value(putval);
+		replaced
+	}
+
+	// Remove a key by driving the transition {Key, Value} -> {Key, ValueTombStone}. Probes with the borrowed
+	// key, like get, since a removal never claims a key slot.
+	// Returns the value that was removed, or None if the key was not present.
+	pub fn remove<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<Ref<'_, V>> where K: Borrow<Q> {
+		let guard = self.pin();
+		let returnval = self.replace_existing(self.hash_key(key), &|k| k.borrow()==key, &|_| true, Value::tombstone());
+		returnval.and_then(|v| Self::value_ref(guard, v))
+	}
+
+	// Remove a key only if it is currently mapped to a value equal to expval. Probes like remove.
+	// Returns true if the mapping was removed.
+	pub fn remove_if_match<Q: Hash + Eq + ?Sized>(&self, key: &Q, expval: V) -> bool where K: Borrow<Q>, V: PartialEq {
+		let _guard = self.pin();
+		self.replace_existing(self.hash_key(key), &|k| k.borrow()==key, &|v| *v==expval, Value::tombstone()).is_some()
+	}
+
+	// Atomically replace the value mapped to key with f(current value); returning None removes the mapping.
+	// Returns the value mapped afterwards. f may run more than once if other writers race on this key.
+	pub fn compute<F: FnMut(Option<&V>) -> Option<V>>(&self, key: K, mut f: F) -> Option<Ref<'_, V>>{
+		self.compute_impl(key, None, |old, _| {
+			match f(old) {
+				Some(newval) => Put(newval),
+				None => Remove
+			}
+		})
+	}
+
+	// Map key to f() unless it is already present. Returns the value mapped afterwards.
+	pub fn compute_if_absent<F: FnMut() -> V>(&self, key: K, mut f: F) -> Ref<'_, V>{
+		self.compute_impl(key, None, |old, _| {
+			match old {
+				Some(_) => Keep,
+				None => Put(f())
+			}
+		}).unwrap()
+	}
+
+	// Replace the value mapped to key with f(value) if key is present; returning None removes it.
+	// Returns the value mapped afterwards.
+	pub fn compute_if_present<F: FnMut(&V) -> Option<V>>(&self, key: K, mut f: F) -> Option<Ref<'_, V>>{
+		self.compute_impl(key, None, |old, _| {
+			match old {
+				Some(oldval) => match f(oldval) {
+					Some(newval) => Put(newval),
+					None => Remove
+				},
+				None => Keep
+			}
+		})
+	}
+
+	// Map key to value if absent, otherwise to f(current value, value); returning None removes it.
+	// Returns the value mapped afterwards.
+	pub fn merge<F: FnMut(&V, &V) -> Option<V>>(&self, key: K, value: V, mut f: F) -> Option<Ref<'_, V>>{
+		self.compute_impl(key, Some(value), |old, given| {
+			match old {
+				Some(oldval) => match f(oldval, given.unwrap()) {
+					Some(newval) => Put(newval),
+					None => Remove
+				},
+				None => PutGiven
+			}
+		})
+	}
+
+	// The CAS retry loop behind the compute family: read the current value, let f decide, then write
+	// with MatchValue against exactly what f was shown. If another writer got in between, start over.
+	// given is boxed once up front so that it survives failed attempts.
+	fn compute_impl<F: FnMut(Option<&V>, Option<&V>) -> ComputeAction<V>>(&self, key: K, given: Option<V>, mut f: F) -> Option<Ref<'_, V>>{
+		unsafe {
+			let guard = self.pin();
+			let key: *mut Key<K> = Box::into_raw(Box::new(Key::<K>::new(key)));
+			let keyptr = (*key).get_key();
+			let fullhash = self.hash_key(&*keyptr);
+			let given: Option<*mut Value<V>> = given.map(|val| Box::into_raw(Box::new(Value::<V>::new(val))));
+			let result = loop {
+				let table = self.get_table_nonatomic();
+				let old = self.get_impl_supply_hash(table, fullhash, &|k| *k==*keyptr);
+				let putval: *mut Value<V> = match f(old.map(|v| &*Value::get_value(v)), given.map(|v| &*Value::get_value(v))) {
+					Keep => break old,
+					Put(newval) => Box::into_raw(Box::new(Value::<V>::new(newval))),
+					PutGiven => given.unwrap(),
+					Remove => {
+						if old.is_none() { break None; }
+						Value::tombstone()
+					}
+				};
+				let expval: *mut Value<V> = old.unwrap_or(Value::tombstone()); // TombStone matches Empty as well
+				let returnval = self.put_if_match_impl(table, key, putval, MatchValue, Some(expval));
+				let success = Self::value_matches(returnval, expval);
+				// If the same value was already there, nothing was written and that value stays mapped.
+				let mapped = if Value::is_value(putval) && (*putval).refs()==0 { returnval } else { putval };
+				if Some(putval)!=given { Self::free_unpublished_value(putval); }
+				if success { break Some(mapped); }
+			};
+			Self::free_unpublished_key(key);
+			if let Some(val) = given {
+				Self::free_unpublished_value(val);
+			}
+			result.and_then(|v| Self::value_ref(guard, v)) // Still alive: a value written and then replaced is retired, not freed
+		}
+	}
+
+	// Look key up once and return a handle for updating exactly the mapping that was observed.
+	pub fn entry(&self, key: K) -> Entry<'_, K, V, S>{
+		unsafe {
+			let guard = self.pin();
+			let key: *mut Key<K> = Box::into_raw(Box::new(Key::<K>::new(key)));
+			let keyptr = (*key).get_key();
+			let fullhash = self.hash_key(&*keyptr);
+			match self.get_slot_supply_hash(self.get_table_nonatomic(), fullhash, &|k| *k==*keyptr) {
+				Some((kvs, idx, v)) => Occupied(OccupiedEntry { _map: self, _guard: guard, _key: key, _hash: fullhash, _kvs: kvs, _idx: idx, _value: v }),
+				None => Vacant(VacantEntry { _map: self, _guard: guard, _key: key, _hash: fullhash })
+			}
+		}
+	}
+
+	// Whether put_if_match_impl with MatchValue found expval: it hands back the value it saw, which
+	// matches on pointer (a copy in a newer table is the same pointer), or as Empty when a TombStone was
+	// expected.
+	fn value_matches(v: *mut Value<V>, expval: *mut Value<V>) -> bool{
+		v==expval || (Value::is_empty(v) && Value::is_tombstone(expval))
+	}
+
+	// Box key, run put_if_match_impl against the current table and return the old value under the same
+	// guard. Afterwards, free the key and putval if they never made it into a table.
+	fn put_boxed(&self, key: K, putval: *mut Value<V>, matchingtype: MatchingTypes, expval: Option<*mut Value<V>>) -> Option<Ref<'_, V>>{
+		let guard = self.pin();
+		let table = self.get_table_nonatomic();
+		let key: *mut Key<K> = Box::into_raw(Box::new(Key::<K>::new(key)));
+		let returnval = self.put_if_match_impl(table, key, putval, matchingtype, expval);
+		Self::free_unpublished_key(key);
+		Self::free_unpublished_value(putval);
+		Self::value_ref(guard, returnval)
+	}
+
+	// None for Empty and TombStone values. guard must have been pinned since value was read; the Ref
+	// takes it over, so value stays alive without pinning again.
+	fn value_ref(guard: Guard<'_>, value: *mut Value<V>) -> Option<Ref<'_, V>>{
+		if !Value::is_value(value) { None }
+		else { Some(Ref { _guard: guard, _ptr: Value::get_value(value) }) }
+	}
+
+	fn put_if_match_impl(&self, kvs: *mut KVs<K,V>, key: *mut Key<K>, putval: *mut Value<V>, matchingtype: MatchingTypes, expval: Option<*mut Value<V>>) -> *mut Value<V> {
+		unsafe {
+			self.put_if_match_impl_supply_hash(kvs, key, self.hash_key(&*(*key).get_key()), putval, matchingtype, expval)
+		}
+	}
+
+	// Compute hash only once
+	fn put_if_match_impl_supply_hash(&self, kvs: *mut KVs<K,V>, key: *mut Key<K>, fullhash: u64, putval: *mut Value<V>, matchingtype: MatchingTypes, expval: Option<*mut Value<V>>) -> *mut Value<V> {
+		unsafe {
+			assert!(!Value::is_empty(putval)); // Never put an Empty value
+			assert!(!Value::is_prime(putval)); // Never put a Prime
+			assert!(matchingtype!=MatchValue || expval.is_some()); // If matchingtype==MatchValue then expval must contain something
+			if let Some(val) = expval { assert!(!Value::is_prime(val)); } // Never expect a Prime
+
+			let len = (*kvs).len();
+			let reprobe_limit = self._config.reprobe_limit(len);
+			let mut idx = (fullhash & (len-1) as u64) as usize;
+			let mut reprobe_cnt: usize = 0;
+			let mut k = (*kvs).get_key_nonatomic_at(idx);
+			let mut v = (*kvs).get_value_nonatomic_at(idx);
+			// Determine if expval is empty
+			let expval_not_empty = matchingtype!=MatchValue || !Value::is_empty(expval.unwrap());
+			// Probing/Re-probing
+			loop {
+				if Key::is_empty(k) { // Found an available key slot
+					if Value::is_tombstone(putval) { return putval; } // Never change KeyEmpty to KeyTombStone
+					if matchingtype==MatchAllNotEmpty { return v; } // The key is absent, so there is nothing to replace
+					(*key).acquire(); // The slot holds a reference as soon as the CAS lands
+					if (*kvs)._table[idx]._key.compare_exchange(k, key, MEMORY_ORDERING, MEMORY_ORDERING).is_ok() { // Add key to the slot
+						(*kvs)._chm._slots.fetch_add(1, MEMORY_ORDERING);	// Add 1 to the number of used slots
+						(*kvs).set_hash_at(idx, fullhash); // Lets other probes skip this slot without comparing keys
+						break;
+					}
+					(*key).release();
+					k = (*kvs).get_key_nonatomic_at(idx);
+					v = (*kvs).get_value_nonatomic_at(idx);
+					assert!(!Key::is_empty(k));
+				}
+				if Self::fast_keyeq(k, (*kvs).get_hash_at(idx), key, fullhash) {
+					break;
+				}
+				// Start re-probing
+				reprobe_cnt += 1;
+				if reprobe_cnt >= reprobe_limit ||
+					Key::is_tombstone(k) // Enter state {KeyTombStone, Empty}; steal exucution path for optimization; let helper save the day.
+					{
+						let newkvs = self.resize(kvs);
+						if expval_not_empty { self.help_copy(); }
+						return self.put_if_match_impl_supply_hash(newkvs, key, fullhash, putval, matchingtype, expval); // Put in the new table instead
+					}
+				idx = (idx+1)&(len-1);
+				k = (*kvs).get_key_nonatomic_at(idx);
+				v = (*kvs).get_value_nonatomic_at(idx);
+			}
+			// End probe/re-probing
+
+			if putval==v { return v; } // Steal path exucution for optimization; let helper save the day.
+			if !(*kvs)._chm.has_newkvs() &&
+				(( Value::is_empty(v) && self._config.table_full(len, (*kvs)._chm._slots.load(MEMORY_ORDERING), reprobe_cnt) ) || // A new key went into a full table: resize it
+				 Value::is_prime(v)) // A copy has begun on this slot, so a resize is under way
+				{
+					self.resize(kvs);
+				}
+			if (*kvs)._chm.has_newkvs() { // Check for the last time if kvs is the newest table
+				let copied_kvs = self.copy_slot_and_check(kvs, idx, expval_not_empty); // Help the copy along, unless this is copy_slot itself
+				return self.put_if_match_impl_supply_hash(copied_kvs, key, fullhash, putval, matchingtype, expval);
+			}
+
+			// This table is the newest, so we can start entering the state machine.
+			loop {
+				assert!(!Value::is_prime(v)); // If there is a Prime than this cannot be the newest table.
+				if matchingtype==MatchAllNotEmpty && !Value::is_value(v) {
+					return v; // Only replace an existing value
+				}
+				if matchingtype==MatchValue {
+					let expval = expval.unwrap();
+					if v!=expval && // if v!= expval (pointer)
+						!(Value::is_empty(v) && Value::is_tombstone(expval)) // If we expect a TombStone and v is empty, it should be a match.
+							{
+								return v; // do nothing, just return the old value.
+							}
+				}
+
+				// Finally, add some values.
+				if self.cas_value(kvs, idx, v, putval, expval_not_empty) {
+					return v; // Still safe to hand back: the caller is pinned
+				}
+				v = (*kvs).get_value_nonatomic_at(idx);
+				if Value::is_prime(v){
+					let copied_kvs = self.copy_slot_and_check(kvs, idx, expval_not_empty);
+					return self.put_if_match_impl_supply_hash(copied_kvs, key, fullhash, putval, matchingtype, expval);
+				}
+			}
+		}
+	}
+
+	// Swap the value of a mapped key for putval, a value or TombStone, in the newest table holding it, if
+	// valeq accepts the current value. Returns the value that was replaced, or None if the key was absent
+	// or its value was rejected. Must be called while pinned.
+	fn replace_existing(&self, fullhash: u64, keyeq: &dyn Fn(&K) -> bool, valeq: &dyn Fn(&V) -> bool, putval: *mut Value<V>) -> Option<*mut Value<V>> {
+		unsafe {
+			loop {
+				let (kvs, idx, v) = self.get_slot_supply_hash(self.get_table_nonatomic(), fullhash, keyeq)?;
+				if (*kvs)._chm.has_newkvs() {
+					self.copy_slot_and_check(kvs, idx, true); // Move the slot along and retry in the new table
+					continue;
+				}
+				if !valeq(&*Value::get_value(v)) { return None; }
+				if self.cas_value(kvs, idx, v, putval, true) {
+					return Some(v);
+				}
+			}
+		}
+	}
+
+	// Swap the value in slot idx of kvs from v to putval, which must not be a Prime. On success the live
+	// count is updated (unless called while copying, where count is false) and v is retired.
+	fn cas_value(&self, kvs: *mut KVs<K,V>, idx: usize, v: *mut Value<V>, putval: *mut Value<V>, count: bool) -> bool{
+		unsafe {
+			let is_value = Value::is_value(putval);
+			if is_value { (*putval).acquire(); } // The slot holds a reference as soon as the CAS lands
+			if (*kvs)._table[idx]._value.compare_exchange(v, putval, MEMORY_ORDERING, MEMORY_ORDERING).is_ok() {
+				if count {
+					if !Value::is_value(v) && is_value { (*kvs)._chm._size.add(1); }
+					if Value::is_value(v) && !is_value { (*kvs)._chm._size.add(-1); }
+				}
+				self.retire_value(v);
+				return true;
+			}
+			if is_value { (*putval).release(); }
+			false
+		}
+	}
+
+	// Lookups probe with the borrowed key directly, so nothing is allocated; e.g. a String key can be
+	// looked up by &str.
+	pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<Ref<'_, V>> where K: Borrow<Q> {
+		let guard = self.pin();
+		let returnvalue = self.get_impl_supply_hash(self.get_table_nonatomic(), self.hash_key(key), &|k| k.borrow()==key);
+		returnvalue.and_then(|v| Self::value_ref(guard, v))
+	}
+
+	// A copy of the value mapped to key, which stays valid however the map changes afterwards.
+	pub fn get_cloned<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<V> where K: Borrow<Q>, V: Clone {
+		self.get(key).map(|value| (*value).clone())
+	}
+
+	pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q> {
+		self.get(key).is_some()
+	}
+
+	// Look up a key already in a table. fullhash is the hash cached beside it, or 0 if that has not been
+	// published yet, in which case the key is hashed here.
+	fn get_impl(&self, kvs: *mut KVs<K,V>, key: *mut Key<K>, fullhash: u64) -> Option<*mut Value<V>> {
+		unsafe {
+			let keyptr = (*key).get_key();
+			let fullhash = if fullhash==0 { self.hash_key(&*keyptr) } else { fullhash };
+			self.get_impl_supply_hash(kvs, fullhash, &|k| std::ptr::eq(k, keyptr) || *k==*keyptr)
+		}
+	}
+
+	// keyeq decides whether a key found in the table is the one being looked up.
+	fn get_impl_supply_hash(&self, kvs: *mut KVs<K,V>, fullhash: u64, keyeq: &dyn Fn(&K) -> bool) -> Option<*mut Value<V>> {
+		self.get_slot_supply_hash(kvs, fullhash, keyeq).map(|(_, _, v)| v)
+	}
+
+	// Like get_impl_supply_hash, but also returns the table and slot index the value was found in.
+	fn get_slot_supply_hash(&self, kvs: *mut KVs<K,V>, fullhash: u64, keyeq: &dyn Fn(&K) -> bool) -> Option<(*mut KVs<K,V>, usize, *mut Value<V>)> {
+		unsafe {
+			let len = (*kvs).len();
+			let mut idx = (fullhash & (len-1) as u64) as usize;
+			let mut reprobe_cnt: usize = 0;
+			loop {
+				let k = (*kvs).get_key_nonatomic_at(idx);
+				let v = (*kvs).get_value_nonatomic_at(idx);
+				if Key::is_empty(k) { return None }
+				let hashk = (*kvs).get_hash_at(idx);
+				if !Key::is_tombstone(k) && (hashk==0 || hashk==fullhash) && keyeq(&*(*k).get_key()) { // Different hashes: different keys
+					if !Value::is_prime(v) {
+						if !Value::is_value(v) { return None }
+						else { return Some((kvs, idx, v)) }
+					}
+					else {
+						let table = self.copy_slot_and_check(kvs, idx, true);
+						return self.get_slot_supply_hash(table, fullhash, keyeq);
+					}
+				}
+				reprobe_cnt += 1;
+				if reprobe_cnt >= self._config.reprobe_limit(len) || Key::is_tombstone(k) {
+					if (*kvs)._chm.has_newkvs() {
+						self.help_copy();
+						return self.get_slot_supply_hash((*kvs)._chm.get_newkvs_nonatomic(), fullhash, keyeq);
+					}
+					else { return None; }
+				}
+				idx = (idx+1) & (len-1);
+			}
+		}
+
+	}
+
+	// Weakly consistent iteration: any resize in progress is finished first, then the newest table is
+	// walked slot by slot and each key's current value is looked up (following any later resize), so
+	// every live key is yielded at most once. Updates made during the walk may or may not be seen.
+	// The iterator keeps the map pinned, so retired memory is not reclaimed while it is alive.
+	pub fn iter(&self) -> Entries<'_, K, V, S> {
+		let guard = self.pin();
+		let kvs = self.finish_copy();
+		Entries { _map: self, _guard: guard, _kvs: kvs, _idx: 0 }
+	}
+
+	pub fn keys(&self) -> Keys<'_, K, V, S> {
+		Keys { _iter: self.iter() }
+	}
+
+	pub fn values(&self) -> Values<'_, K, V, S> {
+		Values { _iter: self.iter() }
+	}
+
+	// Whether slot key k, whose cached hash is hashk, is key. A published hash that differs proves the keys
+	// differ without calling K::eq; 0 means the hash is not known yet, so the keys are compared.
+	fn fast_keyeq(k: *mut Key<K>, hashk: u64, key: *mut Key<K>, hashkey: u64) -> bool {
+		unsafe{
+			k==key ||
+				((hashk==0 || hashk==hashkey) &&
+				 !Key::is_tombstone(k) &&
+				 (*key)==(*k))
+		}
+
+	}
+
+
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> Resizable for NonBlockingHashMap<K, V, S> {
+	type Table = KVs<K,V>;
+
+	fn top(&self) -> &AtomicPtr<KVs<K,V>> {
+		&self._kvs
+	}
+
+	fn min_copy_work(&self) -> usize {
+		self._config._min_copy_work
+	}
+
+	fn promoted(&self, oldkvs: *mut KVs<K,V>) {
+		self._last_resize_ms.store(now_ms(), MEMORY_ORDERING);
+		self._collector.retire(oldkvs as *mut u8, free_box::<KVs<K,V>>);
+	}
+
+	fn copy_slot(&self, oldkvs: *mut KVs<K,V>, idx: usize) -> bool{
+		unsafe {
+
+			let mut key = (*oldkvs).get_key_nonatomic_at(idx);
+
+			// State transition: {Empty, Empty} -> {KeyTombStone, Empty}
+			// ---------------------------------------------------------
+			while Key::is_empty(key) {
+				if (*oldkvs)._table[idx]._key.compare_exchange(key, Key::tombstone(), MEMORY_ORDERING, MEMORY_ORDERING).is_ok() { // Attempt {Empty, Empty} -> {KeyTombStone, Empty}
+					return true;
+				}
+				key = (*oldkvs).get_key_nonatomic_at(idx);
+			}
+			// ---------------------------------------------------------
+
+			// Enter state: {KeyTombStone, Empty}
+			// ---------------------------------------------------------
+			if Key::is_tombstone(key) {
+				return false;
+			}
+			// ---------------------------------------------------------
+
+			// State transition: {Key, Empty} -> {Key, ValueTombPrime} or {Key, ValueTombStone} -> {Key, ValueTombPrime} or {Key, Value}->{Key, Value.prime()}
+			// Priming only tags the pointer, so none of these allocate.
+			// -------------------------------------------------------------------------------------------------------
+			let mut oldvalue = (*oldkvs).get_value_nonatomic_at(idx);
+			while !Value::is_prime(oldvalue){
+				let primed: *mut Value<V> = {
+					if Value::is_value(oldvalue) { Value::prime(oldvalue) }
+					else { Value::tombprime() }
+				};
+				if (*oldkvs)._table[idx]._value.compare_exchange(oldvalue, primed, MEMORY_ORDERING, MEMORY_ORDERING).is_ok() {
+					if Value::is_tombprime(primed) { return true; } // Transition: {Key, Empty} -> {Key, ValueTombPrime} or {Key, ValueTombStone} -> {Key, ValueTombPrime}
+					else { // Transition: {Key, Value} -> {Key, Value'}
+						oldvalue = primed;
+						break;
+					}
+				}
+				oldvalue = (*oldkvs).get_value_nonatomic_at(idx);
+			}
+			// -------------------------------------------------------------------------------------------------------
+
+			// Enter state: {Key, ValueTombPrime}
+			// ---------------------------------------------------------
+			if Value::is_tombprime(oldvalue)  { return false }
+			// ---------------------------------------------------------
+
+			// State transition: {Key, Value.prime()} -> {KeyTombStone, ValueTombPrime}
+			// ---------------------------------------------------------
+			// The new table takes its own reference to the same Value. The hash cached with the key is carried
+			// over, so a resize only rehashes keys whose hash was not published yet.
+			let newkvs = (*oldkvs)._chm.get_newkvs_nonatomic();
+			let fullhash = match (*oldkvs).get_hash_at(idx) {
+				0 => self.hash_key(&*(*key).get_key()),
+				hashk => hashk
+			};
+			self.put_if_match_impl_supply_hash(newkvs, key, fullhash, Value::unprime(oldvalue), MatchValue, Some(Value::empty()));
+
+			// Enter state: {Key, Value.prime()} (intermediate)
+			oldvalue = (*oldkvs).get_value_nonatomic_at(idx); // Check again, just in case...
+			while !Value::is_tombprime(oldvalue) {
+				if (*oldkvs)._table[idx]._value.compare_exchange(oldvalue, Value::tombprime(), MEMORY_ORDERING, MEMORY_ORDERING).is_ok() {
+					self.retire_value(oldvalue); // Drops this table's reference
+					return true;
+				}
+				oldvalue = (*oldkvs).get_value_nonatomic_at(idx);
+			}
+			// ---------------------------------------------------------
+
+			false // State jump to {KeyTombStone, ValueTombPrime} for threads that lost the competition
+		}
+	}
+}
+
+// ---Iterators --------------------------------------------------------------------
+pub struct Entries<'a, K, V, S> {
+	_map: &'a NonBlockingHashMap<K,V,S>,
+	_guard: Guard<'a>,
+	_kvs: *mut KVs<K,V>,
+	_idx: usize,
+}
+
+pub struct Keys<'a, K, V, S> {
+	_iter: Entries<'a, K, V, S>,
+}
+
+pub struct Values<'a, K, V, S> {
+	_iter: Entries<'a, K, V, S>,
+}
+
+// Each item pins on its own, so it stays valid after the iterator has moved on or been dropped.
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Iterator for Entries<'a, K, V, S> {
+	type Item = (Ref<'a, K>, Ref<'a, V>);
+
+	fn next(&mut self) -> Option<(Ref<'a, K>, Ref<'a, V>)> {
+		unsafe {
+			while self._idx < (*self._kvs).len() {
+				let k = (*self._kvs).get_key_nonatomic_at(self._idx);
+				let hash = (*self._kvs).get_hash_at(self._idx);
+				self._idx += 1;
+				if !Key::is_key(k) { continue; }
+				// None: removed, or only a TombStone was copied
+				if let Some(v) = self._map.get_impl(self._kvs, k, hash) {
+					let map = self._map;
+					return Some((Ref { _guard: map.pin(), _ptr: (*k)._key }, Ref { _guard: map.pin(), _ptr: Value::get_value(v) }));
+				}
+			}
+			None
+		}
+	}
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Iterator for Keys<'a, K, V, S> {
+	type Item = Ref<'a, K>;
+
+	fn next(&mut self) -> Option<Ref<'a, K>> {
+		self._iter.next().map(|(k, _)| k)
+	}
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Iterator for Values<'a, K, V, S> {
+	type Item = Ref<'a, V>;
+
+	fn next(&mut self) -> Option<Ref<'a, V>> {
+		self._iter.next().map(|(_, v)| v)
+	}
+}
+
+// ---Entries ----------------------------------------------------------------------
+// An Entry owns the boxed key and the hash computed by entry(), and stays pinned so the observed value
+// cannot be freed under it. Writes through an Entry fail, rather than retry, if another writer changed
+// the mapping since it was observed.
+pub enum Entry<'a, K, V, S> {
+	Occupied(OccupiedEntry<'a, K, V, S>),
+	Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S> {
+	_map: &'a NonBlockingHashMap<K,V,S>,
+	_guard: Guard<'a>,
+	_key: *mut Key<K>,
+	_hash: u64,
+	_kvs: *mut KVs<K,V>, // Table and slot the value was observed in
+	_idx: usize,
+	_value: *mut Value<V>,
+}
+
+pub struct VacantEntry<'a, K, V, S> {
+	_map: &'a NonBlockingHashMap<K,V,S>,
+	_guard: Guard<'a>,
+	_key: *mut Key<K>,
+	_hash: u64,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
+	pub fn key(&self) -> &K {
+		unsafe { &*(*self._key).get_key() }
+	}
+
+	// The value that was observed, which may since have been replaced.
+	pub fn get(&self) -> &V {
+		unsafe { &*Value::get_value(self._value) }
+	}
+
+	// Replace the observed value. Returns false if it had already been replaced or removed.
+	pub fn insert(self, value: V) -> bool {
+		self.cas_observed(Box::into_raw(Box::new(Value::<V>::new(value))))
+	}
+
+	// Remove the mapping if it still holds the observed value. Returns true if it was removed.
+	pub fn remove(self) -> bool {
+		self.cas_observed(Value::tombstone())
+	}
+
+	// CAS the observed slot directly while its table is still the map's and no resize has started. Once
+	// the slot may have been copied, or clear() swapped the table out, go through put_if_match_impl on the
+	// newest table instead, where only the observed value's copy matches.
+	fn cas_observed(&self, putval: *mut Value<V>) -> bool {
+		unsafe {
+			let map = self._map;
+			let success = if map.get_table_nonatomic()==self._kvs && !(*self._kvs)._chm.has_newkvs() { map.cas_value(self._kvs, self._idx, self._value, putval, true) }
+				else {
+					let returnval = map.put_if_match_impl_supply_hash(map.get_table_nonatomic(), self._key, self._hash, putval, MatchValue, Some(self._value));
+					NonBlockingHashMap::<K,V,S>::value_matches(returnval, self._value)
+				};
+			NonBlockingHashMap::<K,V,S>::free_unpublished_value(putval);
+			success
+		}
+	}
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+	pub fn key(&self) -> &K {
+		unsafe { &*(*self._key).get_key() }
+	}
+
+	// Map the key to value via {Key, Empty} -> {Key, Value} (or from TombStone). Returns the inserted
+	// value, or None if another writer mapped the key first.
+	pub fn insert(self, value: V) -> Option<Ref<'a, V>> {
+		unsafe {
+			let map = self._map;
+			let putval: *mut Value<V> = Box::into_raw(Box::new(Value::<V>::new(value)));
+			let expval: *mut Value<V> = Value::tombstone(); // Matches Empty as well
+			let returnval = map.put_if_match_impl_supply_hash(map.get_table_nonatomic(), self._key, self._hash, putval, MatchValue, Some(expval));
+			let result = if NonBlockingHashMap::<K,V,S>::value_matches(returnval, expval) && (*putval).refs()!=0 {
+				NonBlockingHashMap::<K,V,S>::value_ref(map.pin(), putval) // The entry implements Drop, so its guard cannot be moved out
+			}
+			else { None };
+			NonBlockingHashMap::<K,V,S>::free_unpublished_value(putval);
+			result
+		}
+	}
+}
+
+// The key box is freed here unless an insert published it.
+impl<K, V, S> Drop for OccupiedEntry<'_, K, V, S> {
+	fn drop(&mut self) {
+		unsafe {
+			if (*self._key).refs()==0 { drop(Box::from_raw(self._key)); }
+		}
+	}
+}
+
+impl<K, V, S> Drop for VacantEntry<'_, K, V, S> {
+	fn drop(&mut self) {
+		unsafe {
+			if (*self._key).refs()==0 { drop(Box::from_raw(self._key)); }
+		}
+	}
+}
+
+// ---Guarded references -------------------------------------------------------------
+// A key or value borrowed from the map. The Ref keeps the thread pinned, so what it points to is not
+// reclaimed while it is alive, even if another thread replaces or removes it in the meantime. Holding
+// on to a Ref delays the reclamation of everything retired after it was taken.
+pub struct Ref<'a, T> {
+	_guard: Guard<'a>,
+	_ptr: *const T,
+}
+
+impl<T> Deref for Ref<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		unsafe { &*self._ptr }
+	}
+}
+
+impl<'a, T> Ref<'a, T> {
+	// A Ref to part of the referent, under the same guard.
+	fn map<U, F: FnOnce(&T) -> &U>(self, f: F) -> Ref<'a, U> {
+		let ptr: *const U = f(unsafe { &*self._ptr });
+		Ref { _guard: self._guard, _ptr: ptr }
+	}
+}
+
+impl<T: Debug> Debug for Ref<'_, T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		(**self).fmt(f)
+	}
+}
+
+// Free a table and every table a resize has installed after it.
+fn free_table_chain<K,V>(ptr: *mut u8) {
+	unsafe {
+		let mut kvs = ptr as *mut KVs<K,V>;
+		while !kvs.is_null() {
+			let newkvs = (*kvs)._chm.get_newkvs_nonatomic();
+			drop(Box::from_raw(kvs));
+			kvs = newkvs;
+		}
+	}
+}
+
+// Frees the current table and any table a resize has installed after it. Tables that were promoted
+// away, or swapped out by clear(), are owned by the collector, which frees them when it is dropped.
+impl<K,V,S> Drop for NonBlockingHashMap<K,V,S>{
+	fn drop(&mut self){
+		free_table_chain::<K,V>(self._kvs.load(MEMORY_ORDERING) as *mut u8);
+	}
+}
+
+// The number of live mappings, counted by the current table; see capacity() for the table size.
+impl<K,V,S> NonBlockingHashMap<K,V,S>{
+	pub fn len(&self) -> usize{
+		let _guard = self._collector.pin();
+		let sz = unsafe {(*self._kvs.load(MEMORY_ORDERING))._chm._size.sum()};
+		if sz < 0 { 0 } else { sz as usize }
+	}
+
+	pub fn is_empty(&self) -> bool{
+		self.len()==0
+	}
+}
+
+
+// ---Standard traits --------------------------------------------------------------
+impl<K: Eq + Hash, V, S: BuildHasher + Default> Default for NonBlockingHashMap<K, V, S> {
+	fn default() -> NonBlockingHashMap<K, V, S> {
+		NonBlockingHashMap::with_hasher(S::default())
+	}
+}
+
+// Shows the live mappings, as iter() sees them.
+impl<K: Eq + Hash + Debug, V: Debug, S: BuildHasher> Debug for NonBlockingHashMap<K, V, S> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_map().entries(self.iter()).finish()
+	}
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher + Default> FromIterator<(K, V)> for NonBlockingHashMap<K, V, S> {
+	fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> NonBlockingHashMap<K, V, S> {
+		let iter = iter.into_iter();
+		let map = NonBlockingHashMap::with_capacity_and_hasher(iter.size_hint().0, S::default());
+		for (key, value) in iter {
+			map.put(key, value);
+		}
+		map
+	}
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> Extend<(K, V)> for NonBlockingHashMap<K, V, S> {
+	fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+		for (key, value) in iter {
+			self.put(key, value);
+		}
+	}
+}
+
+// A snapshot: the clone holds whatever iter() saw, in a table sized for it.
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher + Clone> Clone for NonBlockingHashMap<K, V, S> {
+	fn clone(&self) -> NonBlockingHashMap<K, V, S> {
+		let map = NonBlockingHashMap::with_config(self.size(), self._hasher.clone(), self._config); // Same policy as the source
+		for (key, value) in self.iter() {
+			map.put((*key).clone(), (*value).clone());
+		}
+		map
+	}
+}
+
+// Maps are equal if they hold the same mappings. Under concurrent updates this compares two snapshots
+// taken at slightly different times.
+impl<K: Eq + Hash, V: PartialEq, S: BuildHasher> PartialEq for NonBlockingHashMap<K, V, S> {
+	fn eq(&self, other: &NonBlockingHashMap<K, V, S>) -> bool {
+		self.len()==other.len() && self.iter().all(|(key, value)| other.get(&*key).is_some_and(|v| *v==*value))
+	}
+}
+
+impl<K: Eq + Hash, V: Eq, S: BuildHasher> Eq for NonBlockingHashMap<K, V, S> {}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> IntoIterator for &'a NonBlockingHashMap<K, V, S> {
+	type Item = (Ref<'a, K>, Ref<'a, V>);
+	type IntoIter = Entries<'a, K, V, S>;
+
+	fn into_iter(self) -> Entries<'a, K, V, S> {
+		self.iter()
+	}
+}
+
+// Owning the map means no other thread can reach it, so its mappings are moved out up front.
+pub struct IntoIter<K, V> {
+	_iter: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+	type Item = (K, V);
+
+	fn next(&mut self) -> Option<(K, V)> {
+		self._iter.next()
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self._iter.size_hint()
+	}
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> IntoIterator for NonBlockingHashMap<K, V, S> {
+	type Item = (K, V);
+	type IntoIter = IntoIter<K, V>;
+
+	fn into_iter(mut self) -> IntoIter<K, V> {
+		IntoIter { _iter: self.take_all().into_iter() }
+	}
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> NonBlockingHashMap<K, V, S> {
+	// Move every live key and value out of the map, leaving their slots holding a Key with a null _key
+	// and a TombStone value. Once the copy is finished and the collector has freed the tables promoted
+	// away, the top table holds the only reference to each key and value.
+	fn take_all(&mut self) -> Vec<(K, V)> {
+		let kvs = {
+			let _guard = self.pin();
+			self.finish_copy()
+		};
+		self._collector.flush();
+		let mut entries = Vec::with_capacity(self.len());
+		unsafe {
+			for idx in 0..(*kvs).len() {
+				let k = (*kvs).get_key_nonatomic_at(idx);
+				let v = (*kvs).get_value_nonatomic_at(idx);
+				if !Key::is_key(k) || !Value::is_value(v) { continue; }
+				assert!((*v).refs()==1);
+				(*kvs)._table[idx]._value.store(Value::tombstone(), MEMORY_ORDERING);
+				let key = Box::from_raw((*k)._key);
+				(*k)._key = ptr::null_mut();
+				entries.push((*key, Box::from_raw(v)._value));
+			}
+		}
+		entries
+	}
+}
+
+
+/****************************************************************************
+ * Tests
+ ****************************************************************************/
+#[cfg(test)]
+mod test {
+	use super::{NonBlockingHashMap, NonBlockingHashMapLong, NonBlockingHashSet, NonBlockingSetInt, NonBlockingIdentityHashMap, NonBlockingHashMapBuilder, Occupied, Vacant};
+	use std::collections::hash_map::RandomState;
+	use crate::keyvalue::{Key, Value, release_value};
+	use crate::kvtable::KVs;
+	use crate::epoch::Collector;
+	use crate::counter::Counter;
+	use std::hash::{BuildHasherDefault, Hasher};
+	use std::sync::atomic::{AtomicUsize, AtomicPtr, Ordering::SeqCst};
+	use std::sync::Arc;
+	use std::ptr;
+	use std::thread;
+
+	#[test]
+	fn test_value_prime_swapping() {
+		unsafe {
+			let value: *mut Value<i32> = Box::into_raw(Box::new(Value::new(10)));
+			let atomicvalue = AtomicPtr::new(value);
+			assert!(!Value::is_prime(atomicvalue.load(SeqCst)));
+			assert!(atomicvalue.compare_exchange(value, Value::prime(value), SeqCst, SeqCst).is_ok());
+			assert!(Value::is_prime(atomicvalue.load(SeqCst)) && Value::is_value(atomicvalue.load(SeqCst)));
+			assert!(*Value::get_value(Value::unprime(atomicvalue.load(SeqCst)))==10);
+			drop(Box::from_raw(value));
+		}
+	}
+
+	static DROPPED: AtomicUsize = AtomicUsize::new(0);
+	struct CountDrop;
+	impl Drop for CountDrop {
+		fn drop(&mut self) { DROPPED.fetch_add(1, SeqCst); }
+	}
+
+	#[test]
+	fn test_kv_destroy(){
+		{
+			let _kv = Key::new(CountDrop);
+			assert!(DROPPED.load(SeqCst)==0);
+		}
+		assert!(DROPPED.load(SeqCst)==1);
+		unsafe {
+			let value: *mut Value<CountDrop> = Box::into_raw(Box::new(Value::new(CountDrop)));
+			(*value).acquire(); // Held by an old table, primed...
+			(*value).acquire(); // ...and by the table it was copied into
+			release_value::<CountDrop>(value as *mut u8);
+			assert!(DROPPED.load(SeqCst)==1);
+			release_value::<CountDrop>(value as *mut u8);
+			assert!(DROPPED.load(SeqCst)==2);
+		}
+	}
+
+	#[test]
+	fn test_vey_eq(){
+		assert!(Key::<i32>::tombstone()==Key::<i32>::tombstone());
+		assert!(Key::<i32>::is_empty(ptr::null_mut()) && Key::is_tombstone(Key::<i32>::tombstone())); // Empty is null, as in a zeroed table
+		assert!(!Key::<i32>::is_key(ptr::null_mut()) && !Key::is_key(Key::<i32>::tombstone()));
+		assert!(Key::<i32>::new(10)==Key::<i32>::new(10));
+		assert!(Key::<i32>::new(5)!=Key::<i32>::new(10));
+	}
+
+	#[test]
+	fn test_value_eq(){
+		assert!(Value::<i32>::empty()==Value::<i32>::empty() && Value::<i32>::tombstone()==Value::<i32>::tombstone());
+		assert!(Value::<i32>::tombprime()==Value::prime(Value::<i32>::tombstone()));
+		assert!(Value::is_tombstone(Value::<i32>::tombprime()) && Value::is_prime(Value::<i32>::tombprime()));
+		assert!(!Value::is_value(Value::<i32>::empty()) && !Value::is_value(Value::<i32>::tombprime()));
+		assert!(Value::unprime(Value::<i32>::tombprime())==Value::tombstone());
+		let value: *mut Value<i32> = Box::into_raw(Box::new(Value::new(10)));
+		assert!(Value::prime(value)!=value && Value::unprime(Value::prime(value))==value);
+		assert!(!Value::is_tombstone(Value::prime(value)) && !Value::is_tombprime(Value::prime(value)));
+		unsafe { drop(Box::from_raw(value)); }
+	}
+
+	static FREED: AtomicUsize = AtomicUsize::new(0);
+	fn count_free(_: *mut u8) { FREED.fetch_add(1, SeqCst); }
+
+	#[test]
+	fn test_epoch_reclamation(){
+		let collector = Collector::new();
+		let guard = collector.pin();
+		collector.retire(std::ptr::null_mut(), count_free);
+		assert!(collector.try_advance());
+		collector.collect();
+		assert!(!collector.try_advance()); // guard is still pinned two epochs back
+		collector.collect();
+		assert!(FREED.load(SeqCst)==0);
+		drop(guard); // Unpinning advances the epoch and frees the garbage
+		assert!(FREED.load(SeqCst)==1);
+		assert!(!collector.has_garbage());
+
+		let barrier = std::sync::Barrier::new(2);
+		thread::scope(|s| {
+			s.spawn(|| {
+				let _guard = collector.pin(); // Counted in this thread's own stripe
+				barrier.wait();
+				barrier.wait();
+			});
+			barrier.wait();
+			collector.retire(std::ptr::null_mut(), count_free);
+			assert!(collector.try_advance());
+			assert!(!collector.try_advance()); // Held back by the other thread's pin
+			barrier.wait();
+		});
+		assert!(FREED.load(SeqCst)==2 && !collector.has_garbage());
+	}
+
+	#[test]
+	fn test_kvs_init(){
+		let kvs = KVs::<i32,i32>::new(10, Arc::new(Counter::new()));
+		assert!(kvs.len()==10);
+		for i in 0..kvs.len() {
+			assert!(Key::is_empty(kvs.get_key_nonatomic_at(i)) && Value::is_empty(kvs.get_value_nonatomic_at(i)) && kvs.get_hash_at(i)==0);
+		}
+	}
+
+	#[test]
+	fn test_hashmap_init(){
+		let map = NonBlockingHashMap::<i32,i32>::new_with_size(10);
+		assert!(map.capacity()==16*4);
+		assert!(map.size()==0 && map.is_empty());
+		unsafe {
+			assert!((*map._kvs.load(SeqCst))._chm._newkvs.load(SeqCst).is_null());
+		}
+	}
+
+	#[test]
+	fn test_hashmap_resize(){
+		let map1 = NonBlockingHashMap::<i32,i32>::new_with_size(10);
+		for i in 0..16 {
+			map1.put(i, i);
+		}
+		let kvs = map1._kvs.load(SeqCst);
+		map1.resize(kvs); // At the load factor, so it grows
+		unsafe {
+			assert!((*(*kvs)._chm._newkvs.load(SeqCst)).len() == 16*4*2);
+		}
+		let map2 = NonBlockingHashMap::<i32,i32>::new_with_size(10);
+		map2.resize(map2._kvs.load(SeqCst));
+		unsafe {
+			assert!((*(*map2._kvs.load(SeqCst))._chm._newkvs.load(SeqCst)).len() == 16*4); // Nothing live: copied at the same size, as a put never shrinks the table
+		}
+	}
+
+	#[test]
+	fn test_hashmap_remove(){
+		let map = NonBlockingHashMap::<i32,i32>::new_with_size(10);
+		assert!(map.remove(&1).is_none());
+		map.put(1, 10);
+		map.put(2, 20);
+		assert!(*map.remove(&1).unwrap()==10);
+		assert!(map.get(&1).is_none());
+		assert!(map.remove(&1).is_none());
+		assert!(!map.remove_if_match(&2, 30));
+		assert!(*map.get(&2).unwrap()==20);
+		assert!(map.remove_if_match(&2, 20) && !map.remove_if_match(&2, 20));
+		assert!(map.get(&2).is_none());
+		map.put(1, 11);
+		assert!(*map.get(&1).unwrap()==11);
+	}
+
+	#[test]
+	fn test_hashmap_shared(){
+		let map = Arc::new(NonBlockingHashMap::<i32,i32>::new());
+		let handles: Vec<_> = (0..4).map(|t| {
+			let child_map = map.clone();
+			thread::spawn(move || {
+				for i in 0..100 {
+					child_map.put(t*100+i, i);
+				}
+			})
+		}).collect();
+		for handle in handles {
+			handle.join().unwrap();
+		}
+		for i in 0..400 {
+			assert!(*map.get(&i).unwrap()==i%100);
+		}
+	}
+
+	#[test]
+	fn test_hashmap_size(){
+		let map = NonBlockingHashMap::<i32,i32>::new();
+		for i in 0..100 {
+			map.put(i, i);
+		}
+		map.put(5, 50); // Overwriting does not change the size
+		assert!(map.size()==100);
+		assert!(map.capacity() > 100); // The table has been resized along the way
+		for i in 0..50 {
+			map.remove(&i);
+		}
+		map.remove(&0);
+		assert!(map.size()==50 && map.len()==50);
+		for i in 50..100 {
+			map.remove(&i);
+		}
+		assert!(map.is_empty());
+	}
+
+	#[test]
+	fn test_hashmap_iter(){
+		let map = NonBlockingHashMap::<i32,i32>::new();
+		for i in 0..100 {
+			map.put(i, i*10);
+		}
+		for i in 0..100 {
+			if i%2==0 { map.remove(&i); }
+		}
+		let mut count = 0;
+		let mut keysum = 0;
+		for (k, v) in map.iter() {
+			assert!(*k%2==1);
+			assert!(*v==*k*10);
+			count += 1;
+			keysum += *k;
+		}
+		assert!(count==50);
+		assert!(keysum==2500); // 1+3+...+99, so every key was seen exactly once
+		assert!(map.keys().count()==50);
+		assert!(map.values().fold(0, |acc, v| acc + *v)==25000);
+	}
+
+	#[test]
+	fn test_hashmap_conditional_put(){
+		let map = NonBlockingHashMap::<i32,i32>::new();
+		assert!(map.replace(&1, 10).is_none());
+		assert!(map.get(&1).is_none());
+		assert!(map.put_if_absent(1, 10).is_none());
+		assert!(*map.put_if_absent(1, 11).unwrap()==10);
+		assert!(*map.get(&1).unwrap()==10);
+		assert!(*map.replace(&1, 12).unwrap()==10);
+		assert!(*map.get(&1).unwrap()==12);
+		assert!(!map.replace_if_eq(&1, 10, 13));
+		assert!(map.replace_if_eq(&1, 12, 13));
+		assert!(*map.get(&1).unwrap()==13);
+		map.remove(&1);
+		assert!(map.replace(&1, 14).is_none() && !map.replace_if_eq(&1, 13, 14));
+		assert!(map.put_if_absent(1, 15).is_none());
+		assert!(*map.get(&1).unwrap()==15);
+		assert!(map.size()==1);
+	}
+
+	#[test]
+	fn test_hashmap_borrowed_lookup(){
+		let map = NonBlockingHashMap::<String,i32>::new();
+		map.put("one".to_string(), 1);
+		map.put("two".to_string(), 2);
+		assert!(*map.get(&"one".to_string()).unwrap()==1);
+		assert!(*map.get("two").unwrap()==2);
+		assert!(map.contains_key("one"));
+		assert!(!map.contains_key("three"));
+		map.remove("one");
+		assert!(!map.contains_key("one"));
+		assert!(map.replace("one", 10).is_none() && *map.replace("two", 20).unwrap()==2);
+		assert!(!map.replace_if_eq("two", 2, 21) && map.replace_if_eq("two", 20, 22));
+		assert!(*map.get("two").unwrap()==22 && map.len()==1);
+		assert!(!map.remove_if_match("two", 20) && map.remove_if_match("two", 22) && map.is_empty());
+	}
+
+	// FNV-1a, to check that the map hashes with whatever hasher it is given.
+	#[derive(Default)]
+	struct FnvHasher(u64);
+	impl Hasher for FnvHasher {
+		fn finish(&self) -> u64 { self.0 }
+		fn write(&mut self, bytes: &[u8]) {
+			if self.0 == 0 { self.0 = 0xcbf29ce484222325; }
+			for b in bytes {
+				self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+			}
+		}
+	}
+
+	#[test]
+	fn test_hashmap_with_hasher(){
+		let map = NonBlockingHashMap::<i32,i32,BuildHasherDefault<FnvHasher>>::with_capacity_and_hasher(10, Default::default());
+		for i in 0..100 {
+			map.put(i, i+1);
+		}
+		for i in 0..100 {
+			assert!(*map.get(&i).unwrap()==i+1);
+		}
+		let other_map = NonBlockingHashMap::<i32,i32,BuildHasherDefault<FnvHasher>>::with_hasher(Default::default());
+		assert!(map.hash_key(&5)==other_map.hash_key(&5)); // Deterministic, unlike the default RandomState
+	}
+
+	#[test]
+	fn test_hashmap_reserve_shrink(){
+		let map = NonBlockingHashMap::<i32,i32>::new();
+		map.reserve(1000);
+		assert!(map.capacity() >= 4000);
+		let cap = map.capacity();
+		for i in 0..1000 {
+			map.put(i, i);
+		}
+		for i in 10..1000 {
+			map.remove(&i);
+		}
+		map.shrink_to_fit();
+		assert!(map.capacity() < cap && map.capacity() >= 40);
+		for i in 0..10 {
+			assert!(*map.get(&i).unwrap()==i);
+		}
+		map.rehash();
+		assert!(map.size()==10 && map.capacity()==128); // Room for 20 mappings, not grown as churn
+		for _ in 0..5 {
+			map.rehash();
+		}
+		assert!(map.capacity()==128);
+		for i in 0..10 {
+			assert!(*map.get(&i).unwrap()==i);
+		}
+	}
+
+	#[test]
+	fn test_hashmap_churn(){
+		let map = NonBlockingHashMap::<i32,i32>::new();
+		for i in 0..200000 {
+			map.put(i, i);
+			if i >= 1000 { map.remove(&(i-1000)); }
+		}
+		assert!(map.len()==1000 && map.capacity() <= 8192); // Resizes drop the removed keys rather than grow
+	}
+
+	#[test]
+	fn test_hashmap_compute(){
+		let map = NonBlockingHashMap::<i32,i32>::new();
+		assert!(*map.compute(1, |old| Some(old.map_or(1, |v| *v+1))).unwrap()==1);
+		assert!(*map.compute(1, |old| Some(old.map_or(1, |v| *v+1))).unwrap()==2);
+		assert!(map.compute(1, |_| None).is_none());
+		assert!(map.get(&1).is_none());
+		assert!(*map.compute_if_absent(2, || 20)==20);
+		assert!(*map.compute_if_absent(2, || 21)==20);
+		assert!(map.compute_if_present(3, |v| Some(*v+1)).is_none());
+		assert!(map.get(&3).is_none());
+		assert!(*map.compute_if_present(2, |v| Some(*v+1)).unwrap()==21);
+		assert!(*map.merge(4, 1, |old, v| Some(*old+*v)).unwrap()==1);
+		assert!(*map.merge(4, 5, |old, v| Some(*old+*v)).unwrap()==6);
+		assert!(map.merge(4, 5, |_, _| None).is_none());
+		assert!(map.size()==1);
+	}
+
+	#[test]
+	fn test_hashmap_compute_concurrent(){
+		let map = Arc::new(NonBlockingHashMap::<i32,i32>::new());
+		let handles: Vec<_> = (0..4).map(|_| {
+			let child_map = map.clone();
+			thread::spawn(move || {
+				for i in 0..1000 {
+					child_map.merge(i%10, 1, |old, v| Some(*old+*v));
+				}
+			})
+		}).collect();
+		for handle in handles {
+			handle.join().unwrap();
+		}
+		for i in 0..10 {
+			assert!(*map.get(&i).unwrap()==400); // No increment was lost
+		}
+	}
+
+	#[test]
+	fn test_hashmap_entry(){
+		let map = NonBlockingHashMap::<i32,i32>::new();
+		match map.entry(1) {
+			Vacant(e) => { assert!(*e.key()==1); assert!(*e.insert(10).unwrap()==10); }
+			Occupied(_) => panic!()
+		}
+		match map.entry(1) {
+			Occupied(e) => { assert!(*e.get()==10); assert!(e.insert(11)); }
+			Vacant(_) => panic!()
+		}
+		assert!(*map.get(&1).unwrap()==11);
+		// A handle whose value was replaced underneath it does not write
+		let stale = map.entry(1);
+		map.put(1, 12);
+		match stale {
+			Occupied(e) => assert!(!e.remove()),
+			Vacant(_) => panic!()
+		}
+		assert!(*map.get(&1).unwrap()==12);
+		match map.entry(1) {
+			Occupied(e) => assert!(e.remove()),
+			Vacant(_) => panic!()
+		}
+		assert!(map.get(&1).is_none());
+		let stale = map.entry(2);
+		map.put(2, 20);
+		match stale {
+			Vacant(e) => assert!(e.insert(21).is_none()),
+			Occupied(_) => panic!()
+		}
+		assert!(*map.get(&2).unwrap()==20);
+		assert!(map.size()==1);
+	}
+
+	#[test]
+	fn test_hashmap_traits(){
+		let map: NonBlockingHashMap<i32,String> = (0..10).map(|i| (i, i.to_string())).collect();
+		assert!(map.size()==10 && *map.get(&3).unwrap()=="3");
+		let mut other = NonBlockingHashMap::<i32,String,RandomState>::default();
+		assert!(other.is_empty());
+		other.extend((0..10).map(|i| (i, i.to_string())));
+		assert!(map==other);
+		other.put(3, "three".to_string());
+		assert!(map!=other);
+		let copy = map.clone();
+		assert!(copy==map);
+		map.remove(&0);
+		assert!(copy.size()==10 && copy!=map); // The clone is a snapshot
+		assert!((&map).into_iter().count()==9);
+		let single: NonBlockingHashMap<i32,i32> = vec![(1, 2)].into_iter().collect();
+		assert!(format!("{:?}", single)=="{1: 2}");
+		let mut owned: Vec<(i32,String)> = copy.into_iter().collect();
+		owned.sort();
+		assert!(owned==(0..10).map(|i| (i, i.to_string())).collect::<Vec<_>>());
+	}
+
+	// Neither Eq nor Debug
+	struct Opaque(i32);
+
+	#[test]
+	fn test_hashmap_unbounded_values(){
+		let map = NonBlockingHashMap::<i32,Opaque>::new();
+		for i in 0..100 {
+			map.put(i, Opaque(i));
+		}
+		assert!(map.get(&7).unwrap().0==7);
+		assert!(map.replace(&7, Opaque(70)).unwrap().0==7);
+		assert!(map.put_if_absent(7, Opaque(0)).unwrap().0==70);
+		assert!(map.compute_if_present(7, |v| Some(Opaque(v.0+1))).unwrap().0==71);
+		assert!(map.remove(&7).unwrap().0==71);
+		assert!(map.size()==99);
+		assert!(map.values().map(|v| v.0).sum::<i32>()==4950-7);
+	}
+
+	static TRACKED_DROPS: AtomicUsize = AtomicUsize::new(0);
+	#[derive(Clone, PartialEq, Debug)]
+	struct Tracked(i32);
+	impl Drop for Tracked {
+		fn drop(&mut self) { TRACKED_DROPS.fetch_add(1, SeqCst); }
+	}
+
+	#[test]
+	fn test_hashmap_guarded_values(){
+		let map = NonBlockingHashMap::<i32,Tracked>::new();
+		map.put(1, Tracked(1));
+		let old = map.get(&1).unwrap();
+		map.put(1, Tracked(2));
+		for _ in 0..10 {
+			map.get(&1); // Each operation unpins, giving the collector a chance to run
+		}
+		assert!(old.0==1); // Replaced, but kept alive by the Ref
+		assert!(TRACKED_DROPS.load(SeqCst)==0);
+		drop(old);
+		for _ in 0..10 {
+			map.get(&1);
+		}
+		assert!(TRACKED_DROPS.load(SeqCst)==1);
+		let copy = map.get_cloned(&1).unwrap();
+		assert!(map.insert(1, Tracked(3))==Some(Tracked(2)));
+		assert!(copy==Tracked(2) && map.insert(2, Tracked(4)).is_none());
+		assert!(map.get_cloned(&3).is_none());
+	}
+
+	static COPIED_DROPS: AtomicUsize = AtomicUsize::new(0);
+	struct Copied;
+	impl Drop for Copied {
+		fn drop(&mut self) { COPIED_DROPS.fetch_add(1, SeqCst); }
+	}
+
+	#[test]
+	fn test_hashmap_resize_drops(){
+		let map = Arc::new(NonBlockingHashMap::<i32,Copied>::new());
+		let mut handles = Vec::new();
+		for t in 0..4 {
+			let map = map.clone();
+			handles.push(thread::spawn(move || {
+				for i in 0..500 {
+					map.put(i, Copied); // Every thread overwrites the same keys while the table grows
+					if i%3==t { map.remove(&i); }
+				}
+			}));
+		}
+		for handle in handles {
+			handle.join().unwrap();
+		}
+		let live = map.len();
+		drop(map); // Values sit in several tables while copied; each must be dropped exactly once
+		assert!(COPIED_DROPS.load(SeqCst)==4*500 && live <= 500);
+	}
+
+	#[test]
+	fn test_hashmaplong(){
+		let map = Arc::new(NonBlockingHashMapLong::new());
+		let mut handles = Vec::new();
+		for t in 0..4u64 {
+			let map = map.clone();
+			handles.push(thread::spawn(move || {
+				for i in 0..1000u64 {
+					map.put(t*1000+i, i);
+				}
+			}));
+		}
+		for handle in handles {
+			handle.join().unwrap();
+		}
+		assert!(map.len()==4000 && map.capacity() > 4000);
+		assert!(map.get(2500)==Some(500) && map.get(4000).is_none());
+		assert!(map.iter().count()==4000);
+
+		let max = NonBlockingHashMapLong::MAX_VALUE;
+		assert!(max==(1<<63)-3 && std::panic::catch_unwind(|| map.put(0, max+1)).is_err()); // 63 bits, less Empty and TombStone
+		assert!(map.put(0, max)==Some(0) && map.get(0)==Some(max));
+		assert!(map.put_if_absent(0, 1)==Some(max) && map.put_if_absent(5000, 1).is_none());
+		assert!(map.replace(6000, 1).is_none() && !map.contains_key(6000));
+		assert!(map.replace_if_eq(5000, 1, 2) && !map.replace_if_eq(5000, 1, 3));
+		assert!(!map.remove_if_match(5000, 1) && map.remove_if_match(5000, 2));
+		assert!(map.remove(NonBlockingHashMapLong::MAX_KEY).is_none());
+		assert!(map.get(u64::MAX).is_none() && !map.contains_key(u64::MAX-1) && map.remove(u64::MAX).is_none()); // Cannot be stored, so absent
+		assert!(!map.replace_if_eq(0, u64::MAX, 1) && !map.remove_if_match(u64::MAX, 0) && map.replace(u64::MAX, 1).is_none());
+		assert!(map.remove(0)==Some(max) && map.get(0).is_none());
+		assert!(map.len()==3999);
+
+		let map = NonBlockingHashMapBuilder::new().load_factor(0.5).reprobe_limit(1).min_copy_work(16).initial_size(1).build_long();
+		for i in 0..20000u64 {
+			map.put(i*4099, i);
+		}
+		assert!(map.len()==20000 && (0..20000u64).all(|i| map.get(i*4099)==Some(i)));
+		assert!(map.capacity() <= 4*65536); // Sized by the builder's load factor, never shrank on the way
+	}
+
+	#[test]
+	fn test_hashset(){
+		let set = Arc::new(NonBlockingHashSet::<String>::new());
+		let mut handles = Vec::new();
+		for t in 0..4 {
+			let set = set.clone();
+			handles.push(thread::spawn(move || {
+				let mut added = 0;
+				for i in 0..100 {
+					if set.insert(format!("{}", (i*7+t)%100)) { added += 1; }
+				}
+				added
+			}));
+		}
+		let added: i32 = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+		assert!(added==100 && set.len()==100 && set.iter().count()==100);
+		assert!(set.contains("42") && !set.insert("42".to_string()));
+		assert!(set.remove("42") && !set.remove("42") && !set.contains("42"));
+
+		let evens: NonBlockingHashSet<i32> = (0..10).filter(|i| i%2==0).collect();
+		let small: NonBlockingHashSet<i32> = (0..5).collect();
+		let union = evens.union(&small);
+		let intersection = evens.intersection(&small);
+		assert!(union.len()==7 && (0..5).all(|i| union.contains(&i)) && union.contains(&8));
+		assert!(intersection.len()==3 && [0, 2, 4].iter().all(|i| intersection.contains(i)));
+		assert!(format!("{:?}", intersection.intersection(&NonBlockingHashSet::new()))=="{}");
+	}
+
+	#[test]
+	fn test_setint(){
+		let set = Arc::new(NonBlockingSetInt::new());
+		assert!(set.capacity() < 1000);
+		let mut handles = Vec::new();
+		for t in 0..4 {
+			let set = set.clone();
+			handles.push(thread::spawn(move || {
+				for i in (t..10000).step_by(4) {
+					assert!(set.insert(i)); // Grows the set while the other threads write and read
+					assert!(set.contains(i));
+				}
+			}));
+		}
+		for handle in handles {
+			handle.join().unwrap();
+		}
+		assert!(set.len()==10000 && set.capacity() >= 10000);
+		assert!(set.iter().eq(0..10000));
+		assert!(!set.insert(62) && set.remove(62) && !set.remove(62) && !set.contains(62));
+		assert!(!set.contains(1<<40) && !set.remove(1<<40) && set.len()==9999);
+		let max = NonBlockingSetInt::MAX_INT;
+		assert!(!set.contains(max) && std::panic::catch_unwind(|| set.insert(max+1)).is_err() && set.len()==9999);
+		assert!(format!("{:?}", NonBlockingSetInt::new_with_size(200))=="{}");
+	}
+
+	#[test]
+	fn test_identityhashmap(){
+		let (a, b) = (Arc::new(Opaque(1)), Arc::new(Opaque(1))); // Equal contents, different objects
+		let map = Arc::new(NonBlockingIdentityHashMap::<Arc<Opaque>, i32>::new());
+		let mut handles = Vec::new();
+		for t in 0..4 {
+			let (map, a, b) = (map.clone(), a.clone(), b.clone());
+			handles.push(thread::spawn(move || {
+				for i in 0..100 {
+					map.put(if (i+t)%2==0 { a.clone() } else { b.clone() }, i);
+				}
+			}));
+		}
+		for handle in handles {
+			handle.join().unwrap();
+		}
+		assert!(map.len()==2 && map.contains_key(&a) && map.contains_key(&b));
+		assert!(map.put(a.clone(), 10).is_some() && *map.get(&a).unwrap()==10);
+		assert!(!map.contains_key(&Opaque(1)));
+		assert!(map.iter().any(|(k, v)| Arc::ptr_eq(&k, &a) && *v==10));
+		assert!(map.replace(&b, 5).is_some() && *map.get(&b).unwrap()==5 && map.replace(&Opaque(1), 6).is_none());
+		assert!(map.remove(&b).is_some() && map.get(&b).is_none() && map.len()==1);
+
+		let names = ["x".to_string(), "x".to_string()];
+		let strs = NonBlockingIdentityHashMap::<&str, usize>::new();
+		strs.put(&names[0], 0);
+		assert!(strs.put_if_absent(&names[1], 1).is_none() && strs.len()==2);
+		assert!(*strs.get(&names[1]).unwrap()==1 && strs.replace(&names[0], 2).is_some());
+	}
+
+	#[test]
+	fn test_hashmap_clear(){
+		let map = Arc::new(NonBlockingHashMap::<i32,i32>::new());
+		for i in 0..1000 {
+			map.put(i, i);
+		}
+		let mut handles = Vec::new();
+		for t in 0..4 {
+			let map = map.clone();
+			handles.push(thread::spawn(move || {
+				for i in 0..1000 {
+					map.put(1000*t+i, i);
+					if i%100==0 { map.clear(); }
+				}
+			}));
+		}
+		for handle in handles {
+			handle.join().unwrap();
+		}
+		assert!(map.len()==map.iter().count());
+		map.clear();
+		assert!(map.is_empty() && map.get(&5).is_none() && map.iter().count()==0);
+		assert!(map.capacity()==NonBlockingHashMap::<i32,i32>::new().capacity()); // Back to the initial size
+		map.put(5, 5);
+		assert!(map.len()==1 && *map.get(&5).unwrap()==5);
+		let entry = match map.entry(5) { Occupied(entry) => entry, Vacant(_) => panic!() };
+		map.clear();
+		assert!(!entry.insert(11) && map.get(&5).is_none()); // The observed table was cleared away
+	}
+
+	static COUNTED_HASHES: AtomicUsize = AtomicUsize::new(0);
+	static COUNTED_EQS: AtomicUsize = AtomicUsize::new(0);
+	struct Counted(i32);
+	impl std::hash::Hash for Counted {
+		fn hash<H: Hasher>(&self, state: &mut H) {
+			COUNTED_HASHES.fetch_add(1, SeqCst);
+			self.0.hash(state);
+		}
+	}
+	impl PartialEq for Counted {
+		fn eq(&self, other: &Counted) -> bool {
+			COUNTED_EQS.fetch_add(1, SeqCst);
+			self.0==other.0
+		}
+	}
+	impl Eq for Counted {}
+
+	#[test]
+	fn test_hashmap_cached_hashes(){
+		let map = NonBlockingHashMap::<Counted,i32>::new_with_size(8);
+		for i in 0..1000 {
+			map.put(Counted(i), i);
+		}
+		let hashes = COUNTED_HASHES.load(SeqCst);
+		assert!(hashes==1000); // Growing the table on the way reused the cached hashes
+		map.reserve(10000);
+		assert!(COUNTED_HASHES.load(SeqCst)==hashes);
+		let eqs = COUNTED_EQS.load(SeqCst);
+		for i in 0..1000 {
+			assert!(*map.get(&Counted(i)).unwrap()==i);
+		}
+		assert!(COUNTED_EQS.load(SeqCst)-eqs==1000); // Only the matching slot is compared
+		let hashes = COUNTED_HASHES.load(SeqCst);
+		assert!(map.iter().count()==1000 && COUNTED_HASHES.load(SeqCst)==hashes); // Iteration looks keys up by their cached hashes
+	}
+
+	#[test]
+	fn test_hashmap_builder(){
+		let map = NonBlockingHashMapBuilder::new().initial_size(100).load_factor(0.5).growth_factor(4).min_size(64)
+			.reprobe_limit(4).min_copy_work(16).hasher(BuildHasherDefault::<FnvHasher>::default()).build::<i32,i32>();
+		assert!(map.capacity()==256); // 100 mappings at half load, rounded up to a power of 2
+		for i in 0..5000 {
+			map.put(i, i);
+		}
+		assert!(map.len()==5000 && map.capacity() >= 10000 && map.capacity().trailing_zeros().is_multiple_of(2)); // Grew by 4 at a time
+		for i in 0..5000 {
+			assert!(*map.get(&i).unwrap()==i);
+		}
+		map.clear();
+		assert!(map.capacity()==256 && map.is_empty()); // The built size, not min_size
+		let big = NonBlockingHashMapBuilder::new().min_size(1024).build::<i32,i32>();
+		big.put(1, 1);
+		let copy = big.clone();
+		assert!(copy.capacity()==1024 && copy==big); // The clone keeps the builder's policy
+		let capped = NonBlockingHashMapBuilder::new().max_initial_size(10).initial_size(1000).build::<i32,i32>();
+		assert!(capped.capacity()==64); // 10 mappings at the default quarter load, rounded up to a power of 2
+	}
+
+	#[test]
+	fn test_hashmap_large_initial_size(){
+		let map = NonBlockingHashMapBuilder::new().load_factor(0.5).initial_size(1_000_000).build::<i32,i32>();
+		assert!(map.capacity()==1<<21); // Past the old 1M cap, and allocated without a box per slot
+		for i in 0..1000 {
+			map.put(i*4099, i);
+		}
+		assert!(map.len()==1000 && map.capacity()==1<<21);
+		assert!(*map.get(&(999*4099)).unwrap()==999 && map.get(&1).is_none());
+	}
+
+	#[test]
+	fn test_hashmap_builder_limits(){
+		let builders = [
+			NonBlockingHashMapBuilder::new().load_factor(0.5),
+			NonBlockingHashMapBuilder::new().load_factor(0.5).reprobe_limit(1),
+			NonBlockingHashMapBuilder::new().reprobe_limit(1),
+			NonBlockingHashMapBuilder::new().reprobe_limit(4),
+			NonBlockingHashMapBuilder::new().load_factor(0.05).reprobe_limit(64).growth_factor(3),
+		];
+		for builder in builders {
+			let map = builder.initial_size(1).build::<i32,i32>();
+			for i in 0..20000 {
+				map.put(i, i);
+			}
+			assert!(map.len()==20000 && (0..20000).all(|i| *map.get(&i).unwrap()==i));
+			assert!(map.capacity() <= 4*map._config.table_size(map._config.slots_for(20000))); // Never shrank and regrew on the way
+		}
+		assert!(std::panic::catch_unwind(|| NonBlockingHashMapBuilder::new().load_factor(0.75)).is_err());
+		assert!(std::panic::catch_unwind(|| NonBlockingHashMapBuilder::new().reprobe_limit(0)).is_err());
+	}
+}
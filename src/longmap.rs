@@ -0,0 +1,470 @@
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize};
+
+use crate::{MatchingTypes, MEMORY_ORDERING, MIN_SIZE, now_ms};
+use crate::builder::Config;
+use crate::MatchingTypes::{MatchAll, MatchAllNotEmpty, MatchValue, FromCopySlot};
+use crate::keyvalue::spread;
+use crate::kvtable::zeroed_slice;
+use crate::copy::{CopyState, CopyTable, Resizable};
+use crate::epoch::{Collector, Guard, free_box};
+use crate::counter::Counter;
+
+// ---Word Encoding ----------------------------------------------------------------------------------------
+// Keys and values live directly in the table's atomic words, so a put allocates nothing. The slot states
+// of the boxed map are reserved bit patterns: a user word x is stored as x+2, leaving 0 for Empty and 1
+// for TombStone, and the top bit of a value word marks it as Prime.
+const EMPTY: u64 = 0;
+const TOMBSTONE: u64 = 1;
+const PRIME: u64 = 1<<63;
+const TOMBPRIME: u64 = TOMBSTONE | PRIME;
+
+fn encode(x: u64) -> u64 {
+	x + 2
+}
+
+fn decode(w: u64) -> u64 {
+	w - 2
+}
+
+fn is_prime(w: u64) -> bool {
+	w & PRIME != 0
+}
+
+// Neither Empty nor TombStone (primed or not).
+fn is_live(w: u64) -> bool {
+	w & !PRIME > TOMBSTONE
+}
+
+// ---Hash Table Layer Node -------------------------------------------------------------------------------
+// The counterpart of KVs/CHM for word-sized entries. A key slot goes Empty -> key, or Empty -> TombStone
+// when a copy closes it; a value slot follows the same Prime/TombPrime protocol as the boxed map.
+pub(crate) struct KVsLong {
+	_ks: Box<[AtomicU64]>,
+	_vs: Box<[AtomicU64]>,
+	_newkvs: AtomicPtr<KVsLong>,
+	_slots: AtomicUsize,
+	_copy: CopyState,
+}
+
+impl KVsLong {
+	fn new(table_size: usize) -> KVsLong {
+		KVsLong {
+			_ks: zeroed_slice(table_size), // EMPTY is 0
+			_vs: zeroed_slice(table_size),
+			_newkvs: AtomicPtr::new(ptr::null_mut()),
+			_slots: AtomicUsize::new(0),
+			_copy: CopyState::new(),
+		}
+	}
+
+	fn key_at(&self, idx: usize) -> u64 {
+		self._ks[idx].load(MEMORY_ORDERING)
+	}
+
+	fn value_at(&self, idx: usize) -> u64 {
+		self._vs[idx].load(MEMORY_ORDERING)
+	}
+
+	fn get_newkvs(&self) -> *mut KVsLong {
+		self._newkvs.load(MEMORY_ORDERING)
+	}
+
+	fn has_newkvs(&self) -> bool {
+		!self.get_newkvs().is_null()
+	}
+
+	fn len(&self) -> usize {
+		self._ks.len()
+	}
+}
+
+impl CopyTable for KVsLong {
+	fn table_len(&self) -> usize {
+		self.len()
+	}
+
+	fn copy_state(&self) -> &CopyState {
+		&self._copy
+	}
+
+	fn newer_table(&self) -> *mut KVsLong {
+		self.get_newkvs()
+	}
+}
+
+// ---Hash Map for Words -----------------------------------------------------------------------------------
+// A NonBlockingHashMap specialised to u64 keys and values, after NonBlockingHashMapLong in the Java
+// original. Keys go through spread directly instead of a BuildHasher. A key may be any u64 up to MAX_KEY,
+// which leaves out the two words taken by Empty and TombStone. Values also give up the top bit to the Prime
+// flag, so they are limited to 63 bits, up to MAX_VALUE = 2^63-3. Storing anything larger panics; looking
+// it up or removing it finds nothing.
+pub struct NonBlockingHashMapLong {
+	_kvs: AtomicPtr<KVsLong>,
+	_last_resize_ms: AtomicU64, // Time of the last table promotion
+	_collector: Collector, // Frees tables that were promoted away
+	_size: Counter, // Number of live mappings
+	_config: Config, // Sizing policy, see NonBlockingHashMapBuilder::build_long
+}
+
+impl NonBlockingHashMapLong {
+	pub const MAX_KEY: u64 = u64::MAX - 2;
+	pub const MAX_VALUE: u64 = PRIME - 3; // Stored as x+2, which must stay clear of the Prime bit
+
+	pub fn new() -> NonBlockingHashMapLong {
+		NonBlockingHashMapLong::new_with_size(MIN_SIZE)
+	}
+
+	pub fn new_with_size(initial_sz: usize) -> NonBlockingHashMapLong {
+		NonBlockingHashMapLong::with_config(initial_sz, Config::default())
+	}
+
+	pub(crate) fn with_config(initial_sz: usize, config: Config) -> NonBlockingHashMapLong {
+		let initial_sz = initial_sz.min(config._max_initial_size);
+		let len = config.table_size(config.slots_for(initial_sz));
+
+		NonBlockingHashMapLong {
+			_kvs: AtomicPtr::new(Box::into_raw(Box::new(KVsLong::new(len)))),
+			_last_resize_ms: AtomicU64::new(now_ms()),
+			_collector: Collector::new(),
+			_size: Counter::new(),
+			_config: config,
+		}
+	}
+
+	// Number of live mappings. Exact when the map is quiescent.
+	pub fn len(&self) -> usize {
+		let sz = self._size.sum();
+		if sz < 0 { 0 } else { sz as usize }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len()==0
+	}
+
+	// Number of slots in the current table.
+	pub fn capacity(&self) -> usize {
+		let _guard = self.pin();
+		unsafe {(*self.get_table_nonatomic()).len()}
+	}
+
+	fn pin(&self) -> Guard<'_> {
+		self._collector.pin()
+	}
+
+	fn get_table_nonatomic(&self) -> *mut KVsLong {
+		self._kvs.load(MEMORY_ORDERING)
+	}
+
+	// Words for a key or value being stored; anything that cannot be stored panics.
+	fn encode_key(key: u64) -> u64 {
+		assert!(key <= Self::MAX_KEY, "key {} is above NonBlockingHashMapLong::MAX_KEY", key);
+		encode(key)
+	}
+
+	fn encode_value(value: u64) -> u64 {
+		assert!(value <= Self::MAX_VALUE, "value {} is above NonBlockingHashMapLong::MAX_VALUE", value);
+		encode(value)
+	}
+
+	// Words for a key or value being looked for: one that cannot be stored cannot be present, so None.
+	fn stored_key(key: u64) -> Option<u64> {
+		if key <= Self::MAX_KEY { Some(encode(key)) } else { None }
+	}
+
+	fn stored_value(value: u64) -> Option<u64> {
+		if value <= Self::MAX_VALUE { Some(encode(value)) } else { None }
+	}
+
+	// None for Empty and TombStone words.
+	fn decode_value(w: u64) -> Option<u64> {
+		if is_live(w) { Some(decode(w)) } else { None }
+	}
+
+	// Returns the value that was replaced, or None if key was absent.
+	pub fn put(&self, key: u64, newval: u64) -> Option<u64> {
+		self.put_if_match(Self::encode_key(key), Self::encode_value(newval), MatchAll, EMPTY)
+	}
+
+	// Put only if key is absent. Returns the value already there, if any.
+	pub fn put_if_absent(&self, key: u64, newval: u64) -> Option<u64> {
+		self.put_if_match(Self::encode_key(key), Self::encode_value(newval), MatchValue, TOMBSTONE)
+	}
+
+	// Put only if key is present. Returns the value that was replaced.
+	pub fn replace(&self, key: u64, newval: u64) -> Option<u64> {
+		let newval = Self::encode_value(newval);
+		self.put_if_match(Self::stored_key(key)?, newval, MatchAllNotEmpty, EMPTY)
+	}
+
+	// Put only if key is mapped to expval.
+	pub fn replace_if_eq(&self, key: u64, expval: u64, newval: u64) -> bool {
+		let newval = Self::encode_value(newval);
+		match (Self::stored_key(key), Self::stored_value(expval)) {
+			(Some(key), Some(w)) => self.put_if_match(key, newval, MatchValue, w)==Some(expval),
+			_ => false
+		}
+	}
+
+	pub fn remove(&self, key: u64) -> Option<u64> {
+		self.put_if_match(Self::stored_key(key)?, TOMBSTONE, MatchAll, EMPTY)
+	}
+
+	// Remove only if key is mapped to expval.
+	pub fn remove_if_match(&self, key: u64, expval: u64) -> bool {
+		match (Self::stored_key(key), Self::stored_value(expval)) {
+			(Some(key), Some(w)) => self.put_if_match(key, TOMBSTONE, MatchValue, w)==Some(expval),
+			_ => false
+		}
+	}
+
+	// key and the values are already encoded.
+	fn put_if_match(&self, key: u64, putval: u64, matchingtype: MatchingTypes, expval: u64) -> Option<u64> {
+		let _guard = self.pin();
+		Self::decode_value(self.put_if_match_impl(self.get_table_nonatomic(), key, putval, matchingtype, expval))
+	}
+
+	// Returns the value word found in key's slot. With MatchValue, expval==TombStone also matches a
+	// missing key; FromCopySlot only fills an Empty slot and leaves the live count alone.
+	fn put_if_match_impl(&self, kvs: *mut KVsLong, key: u64, putval: u64, matchingtype: MatchingTypes, expval: u64) -> u64 {
+		unsafe {
+			assert!(putval!=EMPTY && !is_prime(putval));
+			assert!(!is_prime(expval));
+			let len = (*kvs).len();
+			let reprobe_limit = self._config.reprobe_limit(len);
+			let mut idx = (spread(key) & (len-1) as u64) as usize;
+			let mut reprobe_cnt: usize = 0;
+			loop {
+				let mut k = (*kvs).key_at(idx);
+				if k==EMPTY {
+					if putval==TOMBSTONE { return EMPTY; } // Never claim a slot just to remove
+					if matchingtype==MatchAllNotEmpty { return EMPTY; }
+					match (*kvs)._ks[idx].compare_exchange(EMPTY, key, MEMORY_ORDERING, MEMORY_ORDERING) {
+						Ok(_) => {
+							(*kvs)._slots.fetch_add(1, MEMORY_ORDERING);
+							break;
+						}
+						Err(current) => k = current,
+					}
+				}
+				if k==key { break; }
+				reprobe_cnt += 1;
+				if reprobe_cnt >= reprobe_limit || k==TOMBSTONE { // Out of room, or a copy closed the slot
+					let newkvs = self.resize(kvs);
+					if matchingtype!=FromCopySlot { self.help_copy(); }
+					return self.put_if_match_impl(newkvs, key, putval, matchingtype, expval);
+				}
+				idx = (idx+1)&(len-1);
+			}
+
+			let mut v = (*kvs).value_at(idx);
+			if v==putval { return v; }
+			if !(*kvs).has_newkvs() && ((v==EMPTY && self._config.table_full(len, (*kvs)._slots.load(MEMORY_ORDERING), reprobe_cnt)) || is_prime(v)) {
+				self.resize(kvs);
+			}
+			if (*kvs).has_newkvs() {
+				let newkvs = self.copy_slot_and_check(kvs, idx, matchingtype!=FromCopySlot);
+				return self.put_if_match_impl(newkvs, key, putval, matchingtype, expval);
+			}
+
+			loop {
+				assert!(!is_prime(v));
+				match matchingtype {
+					MatchAllNotEmpty if !is_live(v) => return v,
+					MatchValue if v!=expval && !(v==EMPTY && expval==TOMBSTONE) => return v,
+					FromCopySlot if v!=EMPTY => return v,
+					_ => {}
+				}
+				match (*kvs)._vs[idx].compare_exchange(v, putval, MEMORY_ORDERING, MEMORY_ORDERING) {
+					Ok(_) => {
+						if matchingtype!=FromCopySlot {
+							if !is_live(v) && is_live(putval) { self._size.add(1); }
+							if is_live(v) && !is_live(putval) { self._size.add(-1); }
+						}
+						return v;
+					}
+					Err(current) => v = current,
+				}
+				if is_prime(v) {
+					let newkvs = self.copy_slot_and_check(kvs, idx, matchingtype!=FromCopySlot);
+					return self.put_if_match_impl(newkvs, key, putval, matchingtype, expval);
+				}
+			}
+		}
+	}
+
+	pub fn get(&self, key: u64) -> Option<u64> {
+		let key = Self::stored_key(key)?;
+		let _guard = self.pin();
+		Self::decode_value(self.get_impl(self.get_table_nonatomic(), key))
+	}
+
+	pub fn contains_key(&self, key: u64) -> bool {
+		self.get(key).is_some()
+	}
+
+	// The value word for key, following any resize in progress. Empty if the key is absent.
+	fn get_impl(&self, kvs: *mut KVsLong, key: u64) -> u64 {
+		unsafe {
+			let len = (*kvs).len();
+			let reprobe_limit = self._config.reprobe_limit(len);
+			let mut idx = (spread(key) & (len-1) as u64) as usize;
+			let mut reprobe_cnt: usize = 0;
+			loop {
+				let k = (*kvs).key_at(idx);
+				let v = (*kvs).value_at(idx);
+				if k==EMPTY { return EMPTY; }
+				if k==key {
+					if !is_prime(v) { return v; }
+					let newkvs = self.copy_slot_and_check(kvs, idx, true);
+					return self.get_impl(newkvs, key);
+				}
+				reprobe_cnt += 1;
+				if reprobe_cnt >= reprobe_limit || k==TOMBSTONE {
+					if (*kvs).has_newkvs() {
+						self.help_copy();
+						return self.get_impl((*kvs).get_newkvs(), key);
+					}
+					return EMPTY;
+				}
+				idx = (idx+1)&(len-1);
+			}
+		}
+	}
+
+	fn resize(&self, kvs: *mut KVsLong) -> *mut KVsLong {
+		unsafe {
+			if (*kvs).has_newkvs() {
+				return (*kvs).get_newkvs();
+			}
+			let config = &self._config;
+			let newsz = config.next_table_size((*kvs).len(), self.len(), (*kvs)._slots.load(MEMORY_ORDERING), self._last_resize_ms.load(MEMORY_ORDERING));
+
+			let newkvs: *mut KVsLong = Box::into_raw(Box::new(KVsLong::new(config.table_size(newsz))));
+			match (*kvs)._newkvs.compare_exchange(ptr::null_mut(), newkvs, MEMORY_ORDERING, MEMORY_ORDERING) {
+				Ok(_) => newkvs,
+				Err(current) => {
+					drop(Box::from_raw(newkvs)); // Another thread installed its table first
+					current
+				}
+			}
+		}
+	}
+
+	// Weakly consistent, like NonBlockingHashMap::iter.
+	pub fn iter(&self) -> IterLong<'_> {
+		let guard = self.pin();
+		let kvs = self.finish_copy();
+		IterLong { _map: self, _guard: guard, _kvs: kvs, _idx: 0 }
+	}
+}
+
+impl Resizable for NonBlockingHashMapLong {
+	type Table = KVsLong;
+
+	fn top(&self) -> &AtomicPtr<KVsLong> {
+		&self._kvs
+	}
+
+	fn min_copy_work(&self) -> usize {
+		self._config._min_copy_work
+	}
+
+	fn promoted(&self, oldkvs: *mut KVsLong) {
+		self._last_resize_ms.store(now_ms(), MEMORY_ORDERING);
+		self._collector.retire(oldkvs as *mut u8, free_box::<KVsLong>);
+	}
+
+	// Same state machine as NonBlockingHashMap::copy_slot, but every transition is a single word CAS.
+	// Returns true if this call moved the slot to its final state.
+	fn copy_slot(&self, oldkvs: *mut KVsLong, idx: usize) -> bool {
+		unsafe {
+			// {Empty, Empty} -> {TombStone, Empty}
+			let mut key = (*oldkvs).key_at(idx);
+			while key==EMPTY {
+				match (*oldkvs)._ks[idx].compare_exchange(EMPTY, TOMBSTONE, MEMORY_ORDERING, MEMORY_ORDERING) {
+					Ok(_) => return true,
+					Err(current) => key = current,
+				}
+			}
+			if key==TOMBSTONE { return false; }
+
+			// {Key, Value} -> {Key, Value|Prime}, or {Key, Empty/TombStone} -> {Key, TombPrime}
+			let mut oldvalue = (*oldkvs).value_at(idx);
+			while !is_prime(oldvalue) {
+				let primed = if is_live(oldvalue) { oldvalue | PRIME } else { TOMBPRIME };
+				match (*oldkvs)._vs[idx].compare_exchange(oldvalue, primed, MEMORY_ORDERING, MEMORY_ORDERING) {
+					Ok(_) => {
+						if primed==TOMBPRIME { return true; }
+						oldvalue = primed;
+						break;
+					}
+					Err(current) => oldvalue = current,
+				}
+			}
+			if oldvalue==TOMBPRIME { return false; }
+
+			// Copy the unprimed value into an Empty slot of the new table, then {Key, Value|Prime} -> {Key, TombPrime}
+			self.put_if_match_impl((*oldkvs).get_newkvs(), key, oldvalue & !PRIME, FromCopySlot, EMPTY);
+			while oldvalue!=TOMBPRIME {
+				match (*oldkvs)._vs[idx].compare_exchange(oldvalue, TOMBPRIME, MEMORY_ORDERING, MEMORY_ORDERING) {
+					Ok(_) => return true,
+					Err(current) => oldvalue = current,
+				}
+			}
+			false
+		}
+	}
+}
+
+pub struct IterLong<'a> {
+	_map: &'a NonBlockingHashMapLong,
+	_guard: Guard<'a>,
+	_kvs: *mut KVsLong,
+	_idx: usize,
+}
+
+impl Iterator for IterLong<'_> {
+	type Item = (u64, u64);
+
+	fn next(&mut self) -> Option<(u64, u64)> {
+		unsafe {
+			while self._idx < (*self._kvs).len() {
+				let k = (*self._kvs).key_at(self._idx);
+				self._idx += 1;
+				if k==EMPTY || k==TOMBSTONE { continue; }
+				if let Some(v) = NonBlockingHashMapLong::decode_value(self._map.get_impl(self._kvs, k)) {
+					return Some((decode(k), v));
+				}
+			}
+			None
+		}
+	}
+}
+
+// Frees the current table and any table a resize has installed after it.
+impl Drop for NonBlockingHashMapLong {
+	fn drop(&mut self) {
+		unsafe {
+			let mut kvs = self._kvs.load(MEMORY_ORDERING);
+			while !kvs.is_null() {
+				let newkvs = (*kvs).get_newkvs();
+				drop(Box::from_raw(kvs));
+				kvs = newkvs;
+			}
+		}
+	}
+}
+
+impl Default for NonBlockingHashMapLong {
+	fn default() -> NonBlockingHashMapLong {
+		NonBlockingHashMapLong::new()
+	}
+}
+
+impl fmt::Debug for NonBlockingHashMapLong {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_map().entries(self.iter()).finish()
+	}
+}
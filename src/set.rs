@@ -0,0 +1,127 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{NonBlockingHashMap, Keys, Ref, MIN_SIZE};
+
+// ---Hash Set ---------------------------------------------------------------------
+// A NonBlockingHashMap<T, ()>: members are its keys, so they go through the same key state machine,
+// resizing and reclamation as any map key.
+pub struct NonBlockingHashSet<T, S = RandomState> {
+	_map: NonBlockingHashMap<T, (), S>,
+}
+
+impl<T: Eq + Hash> NonBlockingHashSet<T, RandomState> {
+
+	pub fn new() -> NonBlockingHashSet<T, RandomState> {
+		NonBlockingHashSet::new_with_size(MIN_SIZE)
+	}
+
+	pub fn new_with_size(initial_sz: usize) -> NonBlockingHashSet<T, RandomState> {
+		NonBlockingHashSet { _map: NonBlockingHashMap::new_with_size(initial_sz) }
+	}
+}
+
+impl<T: Eq + Hash, S: BuildHasher> NonBlockingHashSet<T, S> {
+
+	pub fn with_hasher(hasher: S) -> NonBlockingHashSet<T, S> {
+		NonBlockingHashSet { _map: NonBlockingHashMap::with_hasher(hasher) }
+	}
+
+	pub fn with_capacity_and_hasher(initial_sz: usize, hasher: S) -> NonBlockingHashSet<T, S> {
+		NonBlockingHashSet { _map: NonBlockingHashMap::with_capacity_and_hasher(initial_sz, hasher) }
+	}
+
+	// Returns true if value was not already a member.
+	pub fn insert(&self, value: T) -> bool {
+		self._map.put_if_absent(value, ()).is_none()
+	}
+
+	// Returns true if value was a member.
+	pub fn remove<Q: Hash + Eq + ?Sized>(&self, value: &Q) -> bool where T: Borrow<Q> {
+		self._map.remove(value).is_some()
+	}
+
+	pub fn contains<Q: Hash + Eq + ?Sized>(&self, value: &Q) -> bool where T: Borrow<Q> {
+		self._map.contains_key(value)
+	}
+
+	// Number of members; exact when the set is quiescent.
+	pub fn len(&self) -> usize {
+		self._map.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self._map.is_empty()
+	}
+
+	pub fn capacity(&self) -> usize {
+		self._map.capacity()
+	}
+
+	// Weakly consistent, like NonBlockingHashMap::iter.
+	pub fn iter(&self) -> Keys<'_, T, (), S> {
+		self._map.keys()
+	}
+
+	// A new set holding the members of either set, built with a clone of this set's hasher.
+	pub fn union(&self, other: &NonBlockingHashSet<T, S>) -> NonBlockingHashSet<T, S> where T: Clone, S: Clone {
+		let result = NonBlockingHashSet::with_capacity_and_hasher(self.len() + other.len(), self._map._hasher.clone());
+		for value in self.iter().chain(other.iter()) {
+			result.insert((*value).clone());
+		}
+		result
+	}
+
+	// A new set holding the members of both sets, built with a clone of this set's hasher.
+	pub fn intersection(&self, other: &NonBlockingHashSet<T, S>) -> NonBlockingHashSet<T, S> where T: Clone, S: Clone {
+		let result = NonBlockingHashSet::with_capacity_and_hasher(self.len().min(other.len()), self._map._hasher.clone());
+		for value in self.iter() {
+			if other.contains(&*value) {
+				result.insert((*value).clone());
+			}
+		}
+		result
+	}
+}
+
+impl<T: Eq + Hash, S: BuildHasher + Default> Default for NonBlockingHashSet<T, S> {
+	fn default() -> NonBlockingHashSet<T, S> {
+		NonBlockingHashSet { _map: NonBlockingHashMap::default() }
+	}
+}
+
+impl<T: Eq + Hash + Debug, S: BuildHasher> Debug for NonBlockingHashSet<T, S> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_set().entries(self.iter()).finish()
+	}
+}
+
+impl<T: Eq + Hash, S: BuildHasher + Default> FromIterator<T> for NonBlockingHashSet<T, S> {
+	fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> NonBlockingHashSet<T, S> {
+		let set = NonBlockingHashSet::default();
+		for value in iter {
+			set.insert(value);
+		}
+		set
+	}
+}
+
+impl<T: Eq + Hash, S: BuildHasher> Extend<T> for NonBlockingHashSet<T, S> {
+	fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+		for value in iter {
+			self.insert(value);
+		}
+	}
+}
+
+impl<'a, T: Eq + Hash, S: BuildHasher> IntoIterator for &'a NonBlockingHashSet<T, S> {
+	type Item = Ref<'a, T>;
+	type IntoIter = Keys<'a, T, (), S>;
+
+	fn into_iter(self) -> Keys<'a, T, (), S> {
+		self.iter()
+	}
+}
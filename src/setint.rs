@@ -0,0 +1,288 @@
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU64};
+
+use crate::MEMORY_ORDERING;
+use crate::epoch::{Collector, Guard, free_box};
+use crate::counter::Counter;
+use crate::copy::{CopyState, CopyTable, Resizable, MIN_COPY_WORK};
+
+// ---Bit Words --------------------------------------------------------------------------------------------
+// Members are bits in an array of atomic words, 62 to a word. The top two bits of each word drive the copy
+// into a larger array, the same way Prime and Empty drive a table copy in the map: FROZEN marks an old word
+// whose bits have been copied out and may no longer change, PENDING a new word still waiting for them.
+const BITS_PER_WORD: usize = 62;
+const FROZEN: u64 = 1<<63;
+const PENDING: u64 = 1<<62;
+const MEMBER_BITS: u64 = PENDING - 1;
+const MIN_WORDS: usize = 1;
+
+pub(crate) struct Bits {
+	_words: Box<[AtomicU64]>,
+	_newbits: AtomicPtr<Bits>,
+	_copy: CopyState,
+}
+
+impl Bits {
+	// The first oldlen words are PENDING until the copy from a table of that length fills them.
+	fn new(len: usize, oldlen: usize) -> Bits {
+		Bits {
+			_words: (0..len).map(|i| AtomicU64::new(if i < oldlen { PENDING } else { 0 })).collect(),
+			_newbits: AtomicPtr::new(ptr::null_mut()),
+			_copy: CopyState::new(),
+		}
+	}
+
+	fn word_at(&self, idx: usize) -> u64 {
+		self._words[idx].load(MEMORY_ORDERING)
+	}
+
+	fn get_newbits(&self) -> *mut Bits {
+		self._newbits.load(MEMORY_ORDERING)
+	}
+
+	fn has_newbits(&self) -> bool {
+		!self.get_newbits().is_null()
+	}
+
+	fn len(&self) -> usize {
+		self._words.len()
+	}
+}
+
+impl CopyTable for Bits {
+	fn table_len(&self) -> usize {
+		self.len()
+	}
+
+	fn copy_state(&self) -> &CopyState {
+		&self._copy
+	}
+
+	fn newer_table(&self) -> *mut Bits {
+		self.get_newbits()
+	}
+}
+
+// ---Integer Set ------------------------------------------------------------------------------------------
+// A set of non-negative integers, after NonBlockingSetInt in the Java original. Membership tests and
+// updates are a single atomic load or CAS on one word. Adding an integer past the end of the array starts
+// a copy into one at least twice as long; writers help it through before writing, readers copy just the
+// word they need and follow it into the new array. Members go up to MAX_INT, as the Java original indexes
+// by int; inserting anything larger panics rather than sizing the array for it.
+pub struct NonBlockingSetInt {
+	_bits: AtomicPtr<Bits>,
+	_collector: Collector, // Frees arrays that were promoted away
+	_size: Counter,
+}
+
+impl NonBlockingSetInt {
+	pub const MAX_INT: usize = i32::MAX as usize;
+
+	pub fn new() -> NonBlockingSetInt {
+		NonBlockingSetInt::new_with_size(MIN_WORDS*BITS_PER_WORD)
+	}
+
+	// Room for the integers below max without resizing.
+	pub fn new_with_size(max: usize) -> NonBlockingSetInt {
+		assert!(max <= Self::MAX_INT + 1, "size {} is above NonBlockingSetInt::MAX_INT + 1", max);
+		let len = max.div_ceil(BITS_PER_WORD).max(MIN_WORDS);
+		NonBlockingSetInt {
+			_bits: AtomicPtr::new(Box::into_raw(Box::new(Bits::new(len, 0)))),
+			_collector: Collector::new(),
+			_size: Counter::new(),
+		}
+	}
+
+	// Returns true if i was not already a member.
+	pub fn insert(&self, i: usize) -> bool {
+		assert!(i <= Self::MAX_INT, "{} is above NonBlockingSetInt::MAX_INT", i);
+		let (idx, bit) = (i/BITS_PER_WORD, 1<<(i%BITS_PER_WORD));
+		let _guard = self.pin();
+		loop {
+			let bits = self.bits_for(idx);
+			let w = unsafe {(*bits).word_at(idx)};
+			if w & FROZEN != 0 { continue; } // A resize started; bits_for helps it through
+			if w & bit != 0 { return false; }
+			if unsafe {(*bits)._words[idx].compare_exchange(w, w | bit, MEMORY_ORDERING, MEMORY_ORDERING).is_ok()} {
+				self._size.add(1);
+				return true;
+			}
+		}
+	}
+
+	// Returns true if i was a member.
+	pub fn remove(&self, i: usize) -> bool {
+		let (idx, bit) = (i/BITS_PER_WORD, 1<<(i%BITS_PER_WORD));
+		let _guard = self.pin();
+		loop {
+			let bits = self.finish_copy();
+			if idx >= unsafe {(*bits).len()} { return false; }
+			let w = unsafe {(*bits).word_at(idx)};
+			if w & FROZEN != 0 { continue; }
+			if w & bit == 0 { return false; }
+			if unsafe {(*bits)._words[idx].compare_exchange(w, w & !bit, MEMORY_ORDERING, MEMORY_ORDERING).is_ok()} {
+				self._size.add(-1);
+				return true;
+			}
+		}
+	}
+
+	pub fn contains(&self, i: usize) -> bool {
+		let (idx, bit) = (i/BITS_PER_WORD, 1<<(i%BITS_PER_WORD));
+		let _guard = self.pin();
+		let mut bits = self.get_bits_nonatomic();
+		unsafe {
+			loop {
+				if idx >= (*bits).len() {
+					if !(*bits).has_newbits() { return false; }
+					bits = (*bits).get_newbits();
+				}
+				else {
+					let w = (*bits).word_at(idx);
+					if w & FROZEN == 0 { return w & bit != 0; }
+					bits = self.copy_slot_and_check(bits, idx, false); // Copy just this word and follow it
+				}
+			}
+		}
+	}
+
+	// Number of members; exact when the set is quiescent.
+	pub fn len(&self) -> usize {
+		let sz = self._size.sum();
+		if sz < 0 { 0 } else { sz as usize }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len()==0
+	}
+
+	// The integers below this fit without resizing.
+	pub fn capacity(&self) -> usize {
+		let _guard = self.pin();
+		unsafe {(*self.get_bits_nonatomic()).len()*BITS_PER_WORD}
+	}
+
+	fn pin(&self) -> Guard<'_> {
+		self._collector.pin()
+	}
+
+	fn get_bits_nonatomic(&self) -> *mut Bits {
+		self._bits.load(MEMORY_ORDERING)
+	}
+
+	// The newest array once it holds word idx, copying and growing as needed.
+	fn bits_for(&self, idx: usize) -> *mut Bits {
+		loop {
+			let bits = self.finish_copy();
+			if idx < unsafe {(*bits).len()} { return bits; }
+			self.install_newbits(bits, idx+1);
+		}
+	}
+
+	// Install an array of at least newlen words, and at least twice as long up to the words MAX_INT needs, as
+	// the copy target of bits.
+	// If another thread installed one first, ours is thrown away and theirs is returned.
+	fn install_newbits(&self, bits: *mut Bits, newlen: usize) -> *mut Bits {
+		unsafe {
+			let oldlen = (*bits).len();
+			let newlen = newlen.max(oldlen<<1).next_power_of_two().min(Self::MAX_INT/BITS_PER_WORD + 1);
+			if (*bits).has_newbits() {
+				return (*bits).get_newbits();
+			}
+			let newbits: *mut Bits = Box::into_raw(Box::new(Bits::new(newlen, oldlen)));
+			match (*bits)._newbits.compare_exchange(ptr::null_mut(), newbits, MEMORY_ORDERING, MEMORY_ORDERING) {
+				Ok(_) => newbits,
+				Err(current) => {
+					drop(Box::from_raw(newbits)); // Another thread installed its array first
+					current
+				}
+			}
+		}
+	}
+
+	// Weakly consistent, in increasing order: any resize in progress is finished first, then the newest
+	// array is scanned word by word.
+	pub fn iter(&self) -> IterSetInt<'_> {
+		let guard = self.pin();
+		let bits = self.finish_copy();
+		IterSetInt { _guard: guard, _bits: bits, _idx: 0, _word: 0 }
+	}
+}
+
+// Words are copied the way NonBlockingHashMap copies slots.
+impl Resizable for NonBlockingSetInt {
+	type Table = Bits;
+
+	fn top(&self) -> &AtomicPtr<Bits> {
+		&self._bits
+	}
+
+	fn min_copy_work(&self) -> usize {
+		MIN_COPY_WORK
+	}
+
+	fn promoted(&self, oldbits: *mut Bits) {
+		self._collector.retire(oldbits as *mut u8, free_box::<Bits>);
+	}
+
+	// Freeze word idx of oldbits and move its bits into the PENDING word of the new array. Returns true if
+	// this call filled the new word.
+	fn copy_slot(&self, oldbits: *mut Bits, idx: usize) -> bool {
+		unsafe {
+			let w = (*oldbits)._words[idx].fetch_or(FROZEN, MEMORY_ORDERING);
+			let newbits = (*oldbits).get_newbits();
+			(*newbits)._words[idx].compare_exchange(PENDING, w & MEMBER_BITS, MEMORY_ORDERING, MEMORY_ORDERING).is_ok()
+		}
+	}
+}
+
+pub struct IterSetInt<'a> {
+	_guard: Guard<'a>,
+	_bits: *mut Bits,
+	_idx: usize, // Next word to load
+	_word: u64, // Members of word _idx-1 not yet returned
+}
+
+impl Iterator for IterSetInt<'_> {
+	type Item = usize;
+
+	fn next(&mut self) -> Option<usize> {
+		unsafe {
+			while self._word==0 {
+				if self._idx >= (*self._bits).len() { return None; }
+				self._word = (*self._bits).word_at(self._idx) & MEMBER_BITS;
+				self._idx += 1;
+			}
+			let bit = self._word.trailing_zeros() as usize;
+			self._word &= self._word - 1;
+			Some((self._idx-1)*BITS_PER_WORD + bit)
+		}
+	}
+}
+
+// Frees the current array and any array a resize has installed after it.
+impl Drop for NonBlockingSetInt {
+	fn drop(&mut self) {
+		unsafe {
+			let mut bits = self._bits.load(MEMORY_ORDERING);
+			while !bits.is_null() {
+				let newbits = (*bits).get_newbits();
+				drop(Box::from_raw(bits));
+				bits = newbits;
+			}
+		}
+	}
+}
+
+impl Default for NonBlockingSetInt {
+	fn default() -> NonBlockingSetInt {
+		NonBlockingSetInt::new()
+	}
+}
+
+impl fmt::Debug for NonBlockingSetInt {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_set().entries(self.iter()).finish()
+	}
+}